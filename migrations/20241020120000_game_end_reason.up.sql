@@ -0,0 +1,2 @@
+-- Add migration script here
+ALTER TABLE game ADD COLUMN end_reason varchar(15);
@@ -0,0 +1,11 @@
+-- Add migration script here
+CREATE TABLE game_chat (
+  id int GENERATED ALWAYS AS IDENTITY PRIMARY KEY,
+  game int NOT NULL,
+  player int NOT NULL,
+  room varchar(10) NOT NULL,
+  message text NOT NULL,
+  sent_at timestamp NOT NULL,
+  FOREIGN KEY (game) REFERENCES game ON DELETE CASCADE ON UPDATE CASCADE,
+  FOREIGN KEY (player) REFERENCES player ON DELETE CASCADE ON UPDATE CASCADE
+);
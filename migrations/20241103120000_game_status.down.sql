@@ -0,0 +1,4 @@
+-- Add migration script here
+ALTER TABLE game
+DROP COLUMN status,
+DROP COLUMN status_reason;
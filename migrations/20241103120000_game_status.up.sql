@@ -0,0 +1,15 @@
+-- Add migration script here
+ALTER TABLE game
+ADD COLUMN status varchar(10) DEFAULT 'ongoing' NOT NULL CONSTRAINT status_is_known CHECK (
+  status IN (
+    'ongoing',
+    'finished',
+    'aborted',
+    'abandoned',
+    'errored'
+  )
+),
+ADD COLUMN status_reason text;
+UPDATE game
+SET status = 'finished'
+WHERE ended_at IS NOT NULL;
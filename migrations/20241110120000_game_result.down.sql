@@ -0,0 +1,7 @@
+-- Add migration script here
+ALTER TABLE game
+DROP CONSTRAINT result_matches_winner,
+DROP CONSTRAINT termination_is_known,
+DROP COLUMN result;
+ALTER TABLE game
+  RENAME COLUMN termination TO end_reason;
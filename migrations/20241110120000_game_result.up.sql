@@ -0,0 +1,26 @@
+-- Add migration script here
+ALTER TABLE game
+  RENAME COLUMN end_reason TO termination;
+ALTER TABLE game
+ADD COLUMN result varchar(9) CONSTRAINT result_is_known CHECK (result IN ('white_win', 'black_win', 'draw')),
+ADD CONSTRAINT termination_is_known CHECK (
+    termination IN (
+      'checkmate',
+      'resign',
+      'timeout',
+      'stalemate',
+      'repetition',
+      'agreement',
+      'abandonment'
+    )
+  ),
+ADD CONSTRAINT result_matches_winner CHECK (
+    result IS NULL
+    OR (result = 'draw') = (winner IS NULL)
+  );
+UPDATE game
+SET result = CASE
+    WHEN winner = player_white THEN 'white_win'
+    WHEN winner = player_black THEN 'black_win'
+    WHEN status = 'finished' THEN 'draw'
+  END;
@@ -0,0 +1,2 @@
+-- Add migration script here
+ALTER TABLE player DROP COLUMN is_admin;
@@ -0,0 +1,3 @@
+-- Add migration script here
+ALTER TABLE player
+ADD COLUMN is_admin boolean NOT NULL DEFAULT false;
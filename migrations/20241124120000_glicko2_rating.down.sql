@@ -0,0 +1,7 @@
+-- Add migration script here
+DROP TABLE rating_history;
+ALTER TABLE player
+DROP COLUMN rating,
+DROP COLUMN rating_deviation,
+DROP COLUMN rating_volatility,
+ADD COLUMN score int DEFAULT 0 CHECK (score >= 0) NOT NULL;
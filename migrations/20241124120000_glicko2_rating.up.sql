@@ -0,0 +1,18 @@
+-- Add migration script here
+ALTER TABLE player
+ADD COLUMN rating double precision NOT NULL DEFAULT 1500,
+ADD COLUMN rating_deviation double precision NOT NULL DEFAULT 350 CHECK (rating_deviation > 0),
+ADD COLUMN rating_volatility double precision NOT NULL DEFAULT 0.06 CHECK (rating_volatility > 0),
+DROP COLUMN score;
+CREATE TABLE rating_history (
+  id int GENERATED ALWAYS AS IDENTITY PRIMARY KEY,
+  player int NOT NULL,
+  game int NOT NULL,
+  rating double precision NOT NULL,
+  rating_deviation double precision NOT NULL,
+  rating_volatility double precision NOT NULL,
+  rating_change double precision NOT NULL,
+  recorded_at timestamp NOT NULL,
+  FOREIGN KEY (player) REFERENCES player ON DELETE CASCADE ON UPDATE CASCADE,
+  FOREIGN KEY (game) REFERENCES game ON DELETE CASCADE ON UPDATE CASCADE
+);
@@ -0,0 +1,18 @@
+-- Add migration script here
+ALTER TABLE rating_history DROP COLUMN pool;
+ALTER TABLE player
+ADD COLUMN rating double precision NOT NULL DEFAULT 1500,
+ADD COLUMN rating_deviation double precision NOT NULL DEFAULT 350 CHECK (rating_deviation > 0),
+ADD COLUMN rating_volatility double precision NOT NULL DEFAULT 0.06 CHECK (rating_volatility > 0);
+UPDATE player
+SET rating = player_rating.rating,
+  rating_deviation = player_rating.rating_deviation,
+  rating_volatility = player_rating.rating_volatility
+FROM player_rating
+WHERE player_rating.player = player.id
+  AND player_rating.pool = 'standard_rapid';
+DROP TABLE player_rating;
+ALTER TABLE game
+DROP COLUMN variant,
+DROP COLUMN clock_initial,
+DROP COLUMN clock_increment;
@@ -0,0 +1,36 @@
+-- Add migration script here
+ALTER TABLE game
+ADD COLUMN variant varchar(10) NOT NULL DEFAULT 'standard' CONSTRAINT variant_is_known CHECK (variant IN ('standard', 'chess960')),
+ADD COLUMN clock_initial int CHECK (clock_initial > 0),
+ADD COLUMN clock_increment int CHECK (clock_increment >= 0);
+CREATE TABLE player_rating (
+  player int NOT NULL,
+  pool varchar(31) NOT NULL,
+  rating double precision NOT NULL,
+  rating_deviation double precision NOT NULL CHECK (rating_deviation > 0),
+  rating_volatility double precision NOT NULL CHECK (rating_volatility > 0),
+  PRIMARY KEY (player, pool),
+  FOREIGN KEY (player) REFERENCES player ON DELETE CASCADE ON UPDATE CASCADE
+);
+-- Games so far had no clocks, they count as the default 10+0 rapid
+INSERT INTO player_rating (
+    player,
+    pool,
+    rating,
+    rating_deviation,
+    rating_volatility
+  )
+SELECT id,
+  'standard_rapid',
+  rating,
+  rating_deviation,
+  rating_volatility
+FROM player;
+ALTER TABLE player
+DROP COLUMN rating,
+DROP COLUMN rating_deviation,
+DROP COLUMN rating_volatility;
+ALTER TABLE rating_history
+ADD COLUMN pool varchar(31) NOT NULL DEFAULT 'standard_rapid';
+ALTER TABLE rating_history
+ALTER COLUMN pool DROP DEFAULT;
@@ -0,0 +1,2 @@
+-- Add migration script here
+ALTER TABLE game DROP COLUMN rated;
@@ -0,0 +1,3 @@
+-- Add migration script here
+ALTER TABLE game
+ADD COLUMN rated boolean NOT NULL DEFAULT true;
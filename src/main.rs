@@ -1,7 +1,13 @@
 use axum::extract::FromRef;
 use dotenv::dotenv;
 use handlebars::Handlebars;
-use routes::game::matchmaking::matchmaking_state::UserQueue;
+use routes::game::{
+    challenge::challenge_state::Challenges,
+    game_registry::GameRegistry,
+    invite::invite_state::Invites,
+    lobby::lobby_state::Seeks,
+    matchmaking::{matchmaking_state::UserQueue, run_matching},
+};
 use sqlx::{Pool, Postgres};
 use std::{env, fs};
 
@@ -24,6 +30,10 @@ impl FromRef<ServerState> for GlobalState {
 struct ServerState {
     global: GlobalState,
     user_queue: UserQueue,
+    game_registry: GameRegistry,
+    challenges: Challenges,
+    invites: Invites,
+    seeks: Seeks,
     handlebars: Handlebars<'static>,
 }
 
@@ -38,11 +48,23 @@ async fn main() -> anyhow::Result<()> {
         .connect(&connection_string)
         .await
         .unwrap();
+    let global = GlobalState { db_pool };
+    let user_queue = UserQueue::default();
+    let game_registry = GameRegistry::default();
+    tokio::spawn(run_matching(
+        global.clone(),
+        user_queue.clone(),
+        game_registry.clone(),
+    ));
     axum::serve(
         tokio::net::TcpListener::bind("0.0.0.0:3000").await.unwrap(),
         routes::app_routes().with_state(ServerState {
-            global: GlobalState { db_pool },
-            user_queue: UserQueue::default(),
+            global,
+            user_queue,
+            game_registry,
+            challenges: Challenges::default(),
+            invites: Invites::default(),
+            seeks: Seeks::default(),
             handlebars: Handlebars::new(),
         }),
     )
@@ -0,0 +1,41 @@
+use anyhow::anyhow;
+use axum::{
+    extract::{Path, State},
+    Json,
+};
+use serde::Deserialize;
+use serde_json::json;
+
+use crate::{error::AppError, routes::user::jwt::Claims, GlobalState, ServerState};
+
+use super::game_registry::GameRegistry;
+
+#[derive(Deserialize)]
+pub struct AbortRequest {
+    jwt: String,
+    reason: String,
+}
+
+/// Ends a running game without a result, for administrators only
+#[axum::debug_handler(state = ServerState)]
+pub async fn abort_game(
+    State(GlobalState { db_pool }): State<GlobalState>,
+    State(game_registry): State<GameRegistry>,
+    Path(game_id): Path<i32>,
+    Json(request): Json<AbortRequest>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    let claims = Claims::try_from(request.jwt)?;
+    let is_admin = sqlx::query_scalar!("SELECT is_admin FROM player WHERE id = $1", claims.sub)
+        .fetch_optional(&db_pool)
+        .await?
+        .unwrap_or(false);
+    if !is_admin {
+        return Err(anyhow!("Only administrators can abort games").into());
+    }
+    if !game_registry.abort(game_id, request.reason).await {
+        return Err(anyhow!("No such game is running").into());
+    }
+    Ok(Json(json!({
+        "aborted": game_id,
+    })))
+}
@@ -0,0 +1,89 @@
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+};
+
+use axum::extract::FromRef;
+use serde::Serialize;
+use tokio::sync::{oneshot, Mutex};
+
+use crate::{
+    routes::game::{game_type::GameType, piece_color::PieceColor, ws::GameWs},
+    ServerState,
+};
+
+#[derive(Debug)]
+pub struct Challenge {
+    pub challenger: i32,
+    pub challenger_name: String,
+    pub challenged: i32,
+    pub challenger_color: Option<PieceColor>,
+    pub game_type: GameType,
+    pub ws: GameWs,
+    /// Stops watching the challenger's socket once the challenge is answered
+    pub answered: oneshot::Sender<()>,
+}
+
+/// A challenge as shown to the challenged player
+#[derive(Serialize, Debug)]
+pub struct IncomingChallenge {
+    pub id: u64,
+    pub challenger: i32,
+    pub challenger_name: String,
+    pub challenger_color: Option<PieceColor>,
+    pub game_type: GameType,
+}
+
+/// Challenges waiting for an answer, each held open by the challenger's socket
+#[derive(Default, Clone, Debug)]
+pub struct Challenges {
+    challenges: Arc<Mutex<HashMap<u64, Challenge>>>,
+    next_id: Arc<AtomicU64>,
+}
+
+impl Challenges {
+    pub fn next_id(&self) -> u64 {
+        self.next_id.fetch_add(1, Ordering::Relaxed)
+    }
+
+    pub async fn insert(&self, id: u64, challenge: Challenge) {
+        self.challenges.lock().await.insert(id, challenge);
+    }
+
+    pub async fn remove(&self, id: u64) -> Option<Challenge> {
+        self.challenges.lock().await.remove(&id)
+    }
+
+    pub async fn incoming(&self, user_id: i32) -> Vec<IncomingChallenge> {
+        let challenges = self.challenges.lock().await;
+        challenges
+            .iter()
+            .filter(|(_, challenge)| challenge.challenged == user_id)
+            .map(|(id, challenge)| IncomingChallenge {
+                id: *id,
+                challenger: challenge.challenger,
+                challenger_name: challenge.challenger_name.clone(),
+                challenger_color: challenge.challenger_color,
+                game_type: challenge.game_type,
+            })
+            .collect()
+    }
+
+    /// Takes out the challenge so the challenged player can answer it
+    pub async fn take(&self, id: u64, user_id: i32) -> Option<Challenge> {
+        let mut challenges = self.challenges.lock().await;
+        if challenges.get(&id)?.challenged != user_id {
+            return None;
+        }
+        challenges.remove(&id)
+    }
+}
+
+impl FromRef<ServerState> for Challenges {
+    fn from_ref(input: &ServerState) -> Self {
+        input.challenges.clone()
+    }
+}
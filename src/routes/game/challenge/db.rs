@@ -0,0 +1,11 @@
+use sqlx::{Pool, Postgres};
+
+pub async fn get_player_id(
+    db_pool: &Pool<Postgres>,
+    username: &str,
+) -> anyhow::Result<Option<i32>> {
+    let player_id = sqlx::query_scalar!("SELECT id FROM player WHERE username = $1", username)
+        .fetch_optional(db_pool)
+        .await?;
+    Ok(player_id)
+}
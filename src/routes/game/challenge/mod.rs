@@ -0,0 +1,230 @@
+use anyhow::{anyhow, bail};
+use axum::{
+    extract::{
+        ws::{Message, WebSocket},
+        Path, State, WebSocketUpgrade,
+    },
+    response::Response,
+    Json,
+};
+use challenge_state::{Challenge, Challenges, IncomingChallenge};
+use db::get_player_id;
+use serde::Deserialize;
+use serde_json::json;
+use sqlx::{Pool, Postgres};
+use tokio::sync::oneshot;
+use ws_message::{ChallengeRequest, ChallengeServerMsg};
+
+use crate::{error::AppError, routes::user::jwt::Claims, GlobalState, ServerState};
+
+use super::{
+    game_registry::GameRegistry,
+    gameplay::player::GamePlayer,
+    matchmaking::{
+        db::{get_player_summary, PlayerSummary},
+        send_error, start_hosted_game, wait_until_gone,
+    },
+    session_registry::SessionRegistry,
+    ws::GameWs,
+    ws_messages::ServerMsg,
+};
+
+pub mod challenge_state;
+pub mod db;
+pub mod ws_message;
+
+#[derive(Deserialize)]
+pub struct AuthRequest {
+    jwt: String,
+}
+
+/// WebSocket of the challenger, kept open until the challenge is answered
+#[axum::debug_handler(state = ServerState)]
+pub async fn route_handler(
+    ws: WebSocketUpgrade,
+    State(global_state): State<GlobalState>,
+    State(challenges): State<Challenges>,
+    State(sessions): State<SessionRegistry>,
+) -> Response {
+    ws.on_upgrade(|socket: WebSocket| {
+        handle_ws(global_state, GameWs::new(socket), challenges, sessions)
+    })
+}
+
+async fn handle_ws(
+    GlobalState { db_pool }: GlobalState,
+    ws: GameWs,
+    challenges: Challenges,
+    sessions: SessionRegistry,
+) {
+    let Ok(Message::Text(request)) = ws.get().await else {
+        return;
+    };
+    let Ok(request) = serde_json::from_str::<ChallengeRequest>(&request) else {
+        send_error(&ws, ChallengeServerMsg::Error("Invalid challenge".into())).await;
+        return;
+    };
+    let (challenger, challenged) = match find_players(&db_pool, &request).await {
+        Ok(players) => players,
+        Err(error) => {
+            send_error(&ws, ChallengeServerMsg::Error(error.to_string())).await;
+            return;
+        }
+    };
+    if let Err(error) = sessions.start_waiting(challenger.id).await {
+        send_error(&ws, ChallengeServerMsg::Error(error.to_string())).await;
+        return;
+    }
+
+    let id = challenges.next_id();
+    let (answered, answered_rx) = oneshot::channel();
+    let challenger_id = challenger.id;
+    challenges
+        .insert(
+            id,
+            Challenge {
+                challenger: challenger.id,
+                challenger_name: challenger.username,
+                challenged,
+                challenger_color: request.challenger_color,
+                game_type: request.game_type,
+                ws: ws.clone(),
+                answered,
+            },
+        )
+        .await;
+    // Watching only once the challenge is in place, so that leaving early still withdraws it
+    tokio::spawn(wait_for_answer(
+        ws.clone(),
+        answered_rx,
+        challenges,
+        sessions,
+        challenger_id,
+        id,
+    ));
+    let _ = ws
+        .send_as_text(&ServerMsg::Challenge(ChallengeServerMsg::Created(id)))
+        .await;
+}
+
+/// The challenger and the id of the challenged player
+async fn find_players(
+    db_pool: &Pool<Postgres>,
+    request: &ChallengeRequest,
+) -> anyhow::Result<(PlayerSummary, i32)> {
+    request.game_type.check()?;
+    let claims = Claims::try_from(request.jwt.clone()).map_err(|_| anyhow!("Invalid JWT!"))?;
+    let Some(challenged) = get_player_id(db_pool, &request.opponent).await? else {
+        bail!("Unknown player {}", request.opponent);
+    };
+    if challenged == claims.sub {
+        bail!("You can't challenge yourself");
+    }
+    let rating_pool = request.game_type.rating_pool();
+    let challenger = get_player_summary(db_pool, claims.sub, &rating_pool).await?;
+    Ok((challenger, challenged))
+}
+
+async fn wait_for_answer(
+    ws: GameWs,
+    answered: oneshot::Receiver<()>,
+    challenges: Challenges,
+    sessions: SessionRegistry,
+    challenger_id: i32,
+    id: u64,
+) {
+    tokio::select! {
+        _ = wait_until_gone(&ws) => {}
+        _ = answered => return,
+    }
+    // The challenge may have been answered in the meantime
+    if challenges.remove(id).await.is_some() {
+        sessions.stop_waiting(challenger_id).await;
+    }
+}
+
+pub async fn incoming(
+    State(challenges): State<Challenges>,
+    Json(request): Json<AuthRequest>,
+) -> Result<Json<Vec<IncomingChallenge>>, AppError> {
+    let claims = Claims::try_from(request.jwt)?;
+    Ok(Json(challenges.incoming(claims.sub).await))
+}
+
+/// WebSocket of the challenged player, who joins the game right away
+#[axum::debug_handler(state = ServerState)]
+pub async fn accept_route_handler(
+    ws: WebSocketUpgrade,
+    Path(challenge_id): Path<u64>,
+    State(global_state): State<GlobalState>,
+    State(challenges): State<Challenges>,
+    State(game_registry): State<GameRegistry>,
+) -> Response {
+    ws.on_upgrade(move |socket: WebSocket| {
+        handle_accept_ws(
+            global_state,
+            GameWs::new(socket),
+            challenge_id,
+            challenges,
+            game_registry,
+        )
+    })
+}
+
+async fn handle_accept_ws(
+    GlobalState { db_pool }: GlobalState,
+    ws: GameWs,
+    challenge_id: u64,
+    challenges: Challenges,
+    game_registry: GameRegistry,
+) {
+    let Ok(Message::Text(jwt)) = ws.get().await else {
+        return;
+    };
+    let Ok(claims) = Claims::try_from(jwt) else {
+        send_error(&ws, ChallengeServerMsg::Error("Invalid JWT!".into())).await;
+        return;
+    };
+    let sessions = game_registry.sessions();
+    if let Err(error) = sessions.start_waiting(claims.sub).await {
+        send_error(&ws, ChallengeServerMsg::Error(error.to_string())).await;
+        return;
+    }
+    let Some(challenge) = challenges.take(challenge_id, claims.sub).await else {
+        sessions.stop_waiting(claims.sub).await;
+        send_error(&ws, ChallengeServerMsg::Error("No such challenge".into())).await;
+        return;
+    };
+    let _ = challenge.answered.send(());
+    start_hosted_game(
+        &db_pool,
+        &game_registry,
+        GamePlayer::new(challenge.challenger, challenge.ws),
+        challenge.challenger_color,
+        GamePlayer::new(claims.sub, ws),
+        challenge.game_type,
+        true,
+    )
+    .await;
+}
+
+pub async fn decline(
+    State(challenges): State<Challenges>,
+    State(sessions): State<SessionRegistry>,
+    Path(challenge_id): Path<u64>,
+    Json(request): Json<AuthRequest>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    let claims = Claims::try_from(request.jwt)?;
+    let Some(challenge) = challenges.take(challenge_id, claims.sub).await else {
+        return Err(anyhow!("No such challenge").into());
+    };
+    let _ = challenge.answered.send(());
+    sessions.stop_waiting(challenge.challenger).await;
+    let _ = challenge
+        .ws
+        .send_as_text(&ServerMsg::Challenge(ChallengeServerMsg::Declined))
+        .await;
+    Ok(Json(json!({
+        "declined": challenge_id,
+    })))
+}
@@ -0,0 +1,23 @@
+use serde::{Deserialize, Serialize};
+
+use crate::routes::game::{game_type::GameType, piece_color::PieceColor};
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub(crate) enum ChallengeServerMsg {
+    /// The challenge waits for an answer under this id
+    Created(u64),
+    Declined,
+    Error(String),
+}
+
+/// First message on the challenge socket
+#[derive(Deserialize, Debug)]
+pub(crate) struct ChallengeRequest {
+    pub jwt: String,
+    /// Username of the challenged player
+    pub opponent: String,
+    #[serde(default)]
+    pub challenger_color: Option<PieceColor>,
+    #[serde(default)]
+    pub game_type: GameType,
+}
@@ -0,0 +1,103 @@
+use std::time::Duration;
+
+use anyhow::anyhow;
+use axum::extract::ws::Message;
+use futures::{future::BoxFuture, FutureExt};
+use serde::Serialize;
+use tokio::{
+    sync::{mpsc, Mutex},
+    time::Instant,
+};
+
+use super::ws::{GameWs, Heartbeat, PlayerConnection};
+
+/// Messages either side may send before the other one reads them
+const CHANNEL_BUFFER: usize = 64;
+
+/// In-memory connection for driving the server from the same process,
+/// such as from tests or bots.
+/// Dropping either end closes the connection.
+#[derive(Debug)]
+pub struct ChannelConnection {
+    rx: Mutex<mpsc::Receiver<Message>>,
+    tx: mpsc::Sender<Message>,
+}
+
+/// The client's end of a [`ChannelConnection`]
+#[derive(Debug)]
+pub struct ChannelPeer {
+    rx: mpsc::Receiver<Message>,
+    tx: mpsc::Sender<Message>,
+}
+
+impl ChannelConnection {
+    /// Creates a connection to hand over to the server and the client's end of it
+    pub fn pair() -> (GameWs, ChannelPeer) {
+        let (server_tx, client_rx) = mpsc::channel(CHANNEL_BUFFER);
+        let (client_tx, server_rx) = mpsc::channel(CHANNEL_BUFFER);
+        let connection = ChannelConnection {
+            rx: Mutex::new(server_rx),
+            tx: server_tx,
+        };
+        let peer = ChannelPeer {
+            rx: client_rx,
+            tx: client_tx,
+        };
+        (GameWs::from_connection(connection), peer)
+    }
+}
+
+impl PlayerConnection for ChannelConnection {
+    fn get(&self) -> BoxFuture<'_, anyhow::Result<Message>> {
+        async move {
+            self.rx
+                .lock()
+                .await
+                .recv()
+                .await
+                .ok_or(anyhow!("No message"))
+        }
+        .boxed()
+    }
+
+    fn send(&self, message: Message) -> BoxFuture<'_, anyhow::Result<()>> {
+        async move {
+            self.tx
+                .send(message)
+                .await
+                .map_err(|_| anyhow!("The connection is closed"))
+        }
+        .boxed()
+    }
+
+    fn heartbeat(&self) -> BoxFuture<'_, Heartbeat> {
+        // The other end is in the same process, a dropped peer shows up as a closed channel instead
+        async move {
+            Heartbeat {
+                last_seen: Instant::now(),
+                ping_sent_at: None,
+                latency: Some(Duration::ZERO),
+            }
+        }
+        .boxed()
+    }
+}
+
+impl ChannelPeer {
+    /// Waits for the next message from the server, `None` once it hung up
+    pub async fn recv(&mut self) -> Option<Message> {
+        self.rx.recv().await
+    }
+
+    pub async fn send(&self, message: Message) -> anyhow::Result<()> {
+        self.tx
+            .send(message)
+            .await
+            .map_err(|_| anyhow!("The connection is closed"))
+    }
+
+    pub async fn send_as_text<T: Sized + Serialize>(&self, message: &T) -> anyhow::Result<()> {
+        self.send(Message::Text(serde_json::to_string(message)?))
+            .await
+    }
+}
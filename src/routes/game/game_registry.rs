@@ -0,0 +1,212 @@
+use std::{collections::HashMap, sync::Arc};
+
+use anyhow::bail;
+use axum::extract::FromRef;
+use serde::Serialize;
+use tokio::sync::{mpsc, oneshot, watch, Mutex};
+
+use crate::ServerState;
+
+use super::{
+    game_type::GameType,
+    matchmaking::db::PlayerSummary,
+    session_registry::SessionRegistry,
+    ws::GameWs,
+    ws_messages::{GameClientMsg, ServerMsg},
+};
+
+/// Command handled by a running game, in the order it was received
+#[derive(Debug)]
+pub enum GameCommand {
+    /// A message from one of the players, such as a move or a resignation
+    Play { player_id: i32, msg: GameClientMsg },
+    /// A player coming back to the game
+    Reconnect { player_id: i32, ws: GameWs },
+    /// Someone who wants to watch the game
+    Spectate(mpsc::Sender<ServerMsg>),
+    /// A line for the spectators' chat room
+    SpectatorChat {
+        user_id: i32,
+        text: String,
+        reply: oneshot::Sender<anyhow::Result<()>>,
+    },
+    /// Ends the game without a result
+    Abort { reason: String },
+}
+
+/// Public information about a running game
+#[derive(Serialize, Clone, Debug)]
+pub struct LiveGame {
+    pub game_id: i32,
+    pub game_type: GameType,
+    pub white: PlayerSummary,
+    pub black: PlayerSummary,
+    pub move_count: usize,
+    pub spectator_count: usize,
+}
+
+impl LiveGame {
+    pub fn new(
+        game_id: i32,
+        game_type: GameType,
+        white: PlayerSummary,
+        black: PlayerSummary,
+    ) -> Self {
+        Self {
+            game_id,
+            game_type,
+            white,
+            black,
+            move_count: 0,
+            spectator_count: 0,
+        }
+    }
+
+    pub fn combined_rating(&self) -> i32 {
+        self.white.rating + self.black.rating
+    }
+}
+
+/// The running game's end of its registry entry
+#[derive(Debug)]
+pub struct GameLink {
+    pub commands: mpsc::Receiver<GameCommand>,
+    pub live_game: watch::Sender<LiveGame>,
+}
+
+#[derive(Clone, Debug)]
+struct RegisteredGame {
+    commands: mpsc::Sender<GameCommand>,
+    live_game: watch::Receiver<LiveGame>,
+}
+
+/// Running games, indexed by their ids and, through the sessions, by the ids of their players
+#[derive(Default, Clone, Debug)]
+pub struct GameRegistry {
+    games: Arc<Mutex<HashMap<i32, RegisteredGame>>>,
+    sessions: SessionRegistry,
+}
+
+impl GameRegistry {
+    pub async fn register(&self, live_game: LiveGame) -> GameLink {
+        let (commands_tx, commands_rx) = mpsc::channel(8);
+        let game_id = live_game.game_id;
+        let player_ids = [live_game.white.id, live_game.black.id];
+        let (live_game_tx, live_game_rx) = watch::channel(live_game);
+        self.games.lock().await.insert(
+            game_id,
+            RegisteredGame {
+                commands: commands_tx,
+                live_game: live_game_rx,
+            },
+        );
+        self.sessions.start_playing(game_id, player_ids).await;
+        GameLink {
+            commands: commands_rx,
+            live_game: live_game_tx,
+        }
+    }
+
+    /// Lets the game's players go.
+    /// Returns `false` if the game was already unregistered.
+    pub async fn unregister(&self, game_id: i32) -> bool {
+        let Some(game) = self.games.lock().await.remove(&game_id) else {
+            return false;
+        };
+        let player_ids = {
+            let live_game = game.live_game.borrow();
+            [live_game.white.id, live_game.black.id]
+        };
+        self.sessions.stop_playing(game_id, player_ids).await;
+        true
+    }
+
+    pub fn sessions(&self) -> &SessionRegistry {
+        &self.sessions
+    }
+
+    async fn commands(&self, game_id: i32) -> Option<mpsc::Sender<GameCommand>> {
+        let games = self.games.lock().await;
+        games.get(&game_id).map(|game| game.commands.clone())
+    }
+
+    /// Queues the command for the game.
+    /// Returns `false` if there is no such game.
+    pub async fn send(&self, game_id: i32, command: GameCommand) -> bool {
+        let Some(commands) = self.commands(game_id).await else {
+            return false;
+        };
+        commands.send(command).await.is_ok()
+    }
+
+    /// Hands the connection over to the player's running game.
+    /// Gives the connection back if there is no such game.
+    pub async fn reconnect(&self, player_id: i32, ws: GameWs) -> Result<(), GameWs> {
+        let Some(game_id) = self.sessions.game_of(player_id).await else {
+            return Err(ws);
+        };
+        let Some(commands) = self.commands(game_id).await else {
+            return Err(ws);
+        };
+        let Ok(permit) = commands.reserve().await else {
+            return Err(ws);
+        };
+        permit.send(GameCommand::Reconnect { player_id, ws });
+        Ok(())
+    }
+
+    /// Subscribes the channel to the game's messages.
+    /// Returns `false` if there is no such game.
+    pub async fn spectate(&self, game_id: i32, spectator: mpsc::Sender<ServerMsg>) -> bool {
+        self.send(game_id, GameCommand::Spectate(spectator)).await
+    }
+
+    /// Ends the game without a result.
+    /// Returns `false` if there is no such game.
+    pub async fn abort(&self, game_id: i32, reason: String) -> bool {
+        self.send(game_id, GameCommand::Abort { reason }).await
+    }
+
+    /// Posts the line in the game's spectator chat room
+    pub async fn spectator_chat(
+        &self,
+        game_id: i32,
+        user_id: i32,
+        text: String,
+    ) -> anyhow::Result<()> {
+        let (reply, reply_rx) = oneshot::channel();
+        let command = GameCommand::SpectatorChat {
+            user_id,
+            text,
+            reply,
+        };
+        if !self.send(game_id, command).await {
+            bail!("The game is over");
+        }
+        reply_rx.await?
+    }
+
+    pub async fn live_games(&self) -> Vec<LiveGame> {
+        self.games
+            .lock()
+            .await
+            .values()
+            .map(|game| game.live_game.borrow().clone())
+            .collect()
+    }
+
+    /// The running game with the highest rated players
+    pub async fn featured(&self) -> Option<i32> {
+        self.live_games()
+            .await
+            .iter()
+            .max_by_key(|live_game| live_game.combined_rating())
+            .map(|live_game| live_game.game_id)
+    }
+}
+
+impl FromRef<ServerState> for GameRegistry {
+    fn from_ref(input: &ServerState) -> Self {
+        input.game_registry.clone()
+    }
+}
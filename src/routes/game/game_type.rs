@@ -0,0 +1,125 @@
+use std::time::Duration;
+
+use anyhow::bail;
+use serde::{Deserialize, Serialize};
+
+const MIN_INITIAL_TIME: u32 = 30;
+const MAX_INITIAL_TIME: u32 = 3 * 60 * 60;
+const MAX_INCREMENT: u32 = 180;
+/// Moves assumed per game when estimating how long it lasts
+const ESTIMATED_MOVES: u32 = 40;
+
+#[derive(PartialEq, Eq, Hash, Clone, Copy, Serialize, Deserialize, Debug)]
+pub struct TimeControl {
+    pub initial_secs: u32,
+    /// Added to the player's clock after each of their moves
+    pub increment_secs: u32,
+}
+
+impl TimeControl {
+    pub fn initial(&self) -> Duration {
+        Duration::from_secs(self.initial_secs.into())
+    }
+
+    pub fn increment(&self) -> Duration {
+        Duration::from_secs(self.increment_secs.into())
+    }
+}
+
+#[derive(PartialEq, Eq, Hash, Clone, Copy, Serialize, Deserialize, Debug, Default)]
+pub enum Variant {
+    #[default]
+    Standard,
+    /// Standard rules with the back rank pieces shuffled
+    Chess960,
+}
+
+impl Variant {
+    pub fn get_name<'a>(&self) -> &'a str {
+        match *self {
+            Variant::Standard => "standard",
+            Variant::Chess960 => "chess960",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "standard" => Some(Variant::Standard),
+            "chess960" => Some(Variant::Chess960),
+            _ => None,
+        }
+    }
+}
+
+/// Speed category of a time control, ratings are kept per category
+#[derive(PartialEq, Clone, Copy, Serialize, Deserialize, Debug)]
+pub enum Speed {
+    Bullet,
+    Blitz,
+    Rapid,
+    Classical,
+}
+
+impl Speed {
+    pub fn get_name<'a>(&self) -> &'a str {
+        match *self {
+            Speed::Bullet => "bullet",
+            Speed::Blitz => "blitz",
+            Speed::Rapid => "rapid",
+            Speed::Classical => "classical",
+        }
+    }
+}
+
+/// The kind of game players are looking for, only players wanting the same one are paired
+#[derive(PartialEq, Eq, Hash, Clone, Copy, Serialize, Deserialize, Debug)]
+pub struct GameType {
+    pub time_control: TimeControl,
+    #[serde(default)]
+    pub variant: Variant,
+}
+
+impl Default for GameType {
+    /// 10+0 rapid
+    fn default() -> Self {
+        Self {
+            time_control: TimeControl {
+                initial_secs: 10 * 60,
+                increment_secs: 0,
+            },
+            variant: Variant::Standard,
+        }
+    }
+}
+
+impl GameType {
+    pub fn check(&self) -> anyhow::Result<()> {
+        let TimeControl {
+            initial_secs,
+            increment_secs,
+        } = self.time_control;
+        if !(MIN_INITIAL_TIME..=MAX_INITIAL_TIME).contains(&initial_secs) {
+            bail!("The initial time must be between {MIN_INITIAL_TIME} and {MAX_INITIAL_TIME} seconds");
+        }
+        if increment_secs > MAX_INCREMENT {
+            bail!("The increment can't be longer than {MAX_INCREMENT} seconds");
+        }
+        Ok(())
+    }
+
+    pub fn speed(&self) -> Speed {
+        let estimated_secs =
+            self.time_control.initial_secs + ESTIMATED_MOVES * self.time_control.increment_secs;
+        match estimated_secs {
+            0..=179 => Speed::Bullet,
+            180..=479 => Speed::Blitz,
+            480..=1499 => Speed::Rapid,
+            _ => Speed::Classical,
+        }
+    }
+
+    /// Name of the ratings that games of this type count towards
+    pub fn rating_pool(&self) -> String {
+        format!("{}_{}", self.variant.get_name(), self.speed().get_name())
+    }
+}
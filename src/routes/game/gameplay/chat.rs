@@ -0,0 +1,70 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    time::Duration,
+};
+
+use serde::{Deserialize, Serialize};
+use tokio::time::Instant;
+
+use super::error::GameError;
+
+const MAX_CHAT_LINE_LENGTH: usize = 300;
+/// Lines a single user may send within [`CHAT_RATE_WINDOW`]
+const CHAT_RATE_LIMIT: usize = 5;
+const CHAT_RATE_WINDOW: Duration = Duration::from_secs(10);
+
+#[derive(PartialEq, Clone, Copy, Serialize, Deserialize, Debug)]
+pub enum ChatRoom {
+    Players,
+    Spectators,
+}
+
+impl ChatRoom {
+    pub fn get_name<'a>(&self) -> &'a str {
+        match *self {
+            ChatRoom::Players => "players",
+            ChatRoom::Spectators => "spectators",
+        }
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug)]
+pub struct ChatLine {
+    pub room: ChatRoom,
+    pub author: i32,
+    pub text: String,
+}
+
+/// Keeps track of when each user sent their recent lines
+#[derive(Default, Debug)]
+pub struct ChatLimiter {
+    sent_at: HashMap<i32, VecDeque<Instant>>,
+}
+
+impl ChatLimiter {
+    /// Checks the line against the length and rate limits, returning it trimmed
+    pub fn check(&mut self, author: i32, text: &str) -> Result<String, GameError> {
+        let text = text.trim();
+        if text.is_empty() {
+            return Err(GameError::EmptyChatMessage);
+        }
+        if text.chars().count() > MAX_CHAT_LINE_LENGTH {
+            return Err(GameError::ChatMessageTooLong {
+                max_length: MAX_CHAT_LINE_LENGTH,
+            });
+        }
+        let now = Instant::now();
+        let sent_at = self.sent_at.entry(author).or_default();
+        while sent_at
+            .front()
+            .is_some_and(|sent| now.duration_since(*sent) > CHAT_RATE_WINDOW)
+        {
+            sent_at.pop_front();
+        }
+        if sent_at.len() >= CHAT_RATE_LIMIT {
+            return Err(GameError::ChatRateLimited);
+        }
+        sent_at.push_back(now);
+        Ok(text.to_owned())
+    }
+}
@@ -1,40 +1,71 @@
-use anyhow::{anyhow, bail};
+use rand::{seq::SliceRandom, Rng};
 
-use crate::routes::game::piece_color::PieceColor;
+use crate::routes::game::{game_type::Variant, piece_color::PieceColor};
 
 use super::{
+    error::GameError,
     piece::{Piece, PieceType},
     position::Position,
 };
 
+const STANDARD_BACK_RANK: [PieceType; 8] = [
+    PieceType::Rook,
+    PieceType::Knight,
+    PieceType::Bishop,
+    PieceType::Queen,
+    PieceType::King,
+    PieceType::Bishop,
+    PieceType::Knight,
+    PieceType::Rook,
+];
+
+/// Bishops on squares of both colors and the king between the rooks
+fn chess960_back_rank() -> [PieceType; 8] {
+    let mut rng = rand::thread_rng();
+    let mut back_rank = [None; 8];
+    back_rank[rng.gen_range(0..4) * 2] = Some(PieceType::Bishop);
+    back_rank[rng.gen_range(0..4) * 2 + 1] = Some(PieceType::Bishop);
+    let free_columns = |back_rank: &[Option<PieceType>; 8]| {
+        (0..8)
+            .filter(|column| back_rank[*column].is_none())
+            .collect::<Vec<_>>()
+    };
+    for piece_type in [PieceType::Queen, PieceType::Knight, PieceType::Knight] {
+        let column = *free_columns(&back_rank).choose(&mut rng).unwrap();
+        back_rank[column] = Some(piece_type);
+    }
+    let remaining = [PieceType::Rook, PieceType::King, PieceType::Rook];
+    for (column, piece_type) in free_columns(&back_rank).into_iter().zip(remaining) {
+        back_rank[column] = Some(piece_type);
+    }
+    back_rank.map(|piece_type| piece_type.unwrap())
+}
+
 #[derive(Clone, Debug)]
 pub struct ChessBoard {
     pub pieces: Vec<Piece>,
 }
 
 impl ChessBoard {
-    pub fn new() -> Self {
+    pub fn new(variant: Variant) -> Self {
+        let back_rank = match variant {
+            Variant::Standard => STANDARD_BACK_RANK,
+            Variant::Chess960 => chess960_back_rank(),
+        };
         let mut pieces: Vec<Piece> = Vec::with_capacity(32);
         for color in [PieceColor::White, PieceColor::Black] {
             for column in 0..8 {
                 pieces.push(Piece::new(PieceType::Pawn, color, column));
             }
-            pieces.push(Piece::new(PieceType::Rook, color, 0));
-            pieces.push(Piece::new(PieceType::Rook, color, 7));
-            pieces.push(Piece::new(PieceType::Knight, color, 1));
-            pieces.push(Piece::new(PieceType::Knight, color, 6));
-            pieces.push(Piece::new(PieceType::Bishop, color, 2));
-            pieces.push(Piece::new(PieceType::Bishop, color, 5));
-            pieces.push(Piece::new(PieceType::Queen, color, 3));
-            pieces.push(Piece::new(PieceType::King, color, 4));
+            for (column, piece_type) in back_rank.into_iter().enumerate() {
+                pieces.push(Piece::new(piece_type, color, column as i8));
+            }
         }
         Self { pieces }
     }
 
-    pub fn find_own_piece_at_mut(&mut self, position: Position) -> Option<&mut Piece> {
-        self.pieces
-            .iter_mut()
-            .find(move |piece| piece.position == position)
+    pub fn piece_at(&self, position: Position) -> Option<&Piece> {
+        self.pieces.iter().find(|piece| piece.position == position)
     }
 
     pub fn find_king(&self, color: PieceColor) -> Option<&Piece> {
@@ -43,11 +74,34 @@ impl ChessBoard {
             .find(|piece| piece.color == color && piece.piece_type == PieceType::King)
     }
 
-    pub fn remove_piece(&mut self, position: Position, color: PieceColor) -> Option<Piece> {
-        let position = self.pieces.iter().position(move |current_piece| {
-            current_piece.position == position && current_piece.color == color
-        })?;
-        Some(self.pieces.swap_remove(position))
+    /// Piece placement field of the FEN notation
+    pub fn fen_placement(&self) -> String {
+        let mut placement = String::new();
+        for row in (0..8).rev() {
+            let mut empty_tiles = 0;
+            for column in 0..8 {
+                let piece = self
+                    .pieces
+                    .iter()
+                    .find(|piece| piece.position == Position::new(column, row));
+                let Some(piece) = piece else {
+                    empty_tiles += 1;
+                    continue;
+                };
+                if empty_tiles > 0 {
+                    placement.push_str(&empty_tiles.to_string());
+                    empty_tiles = 0;
+                }
+                placement.push(piece.piece_type.get_fen_char(piece.color));
+            }
+            if empty_tiles > 0 {
+                placement.push_str(&empty_tiles.to_string());
+            }
+            if row > 0 {
+                placement.push('/');
+            }
+        }
+        placement
     }
 
     pub fn is_path_clear(&self, from: Position, to: Position) -> bool {
@@ -102,20 +156,214 @@ impl ChessBoard {
         true
     }
 
-    pub async fn move_piece(
-        &mut self,
+    /// Whether any piece of the color could capture on the square
+    pub fn is_attacked(&self, square: Position, by: PieceColor) -> bool {
+        self.pieces.iter().any(|piece| {
+            piece.color == by
+                && piece.can_reach(square, true)
+                && self.is_path_clear(piece.position, square)
+        })
+    }
+
+    pub fn is_in_check(&self, color: PieceColor) -> bool {
+        self.find_king(color)
+            .is_some_and(|king| self.is_attacked(king.position, color.invert()))
+    }
+
+    /// Checks the move against the rules without making it
+    pub fn check_move(
+        &self,
         player_color: PieceColor,
         from: Position,
         to: Position,
-    ) -> anyhow::Result<(PieceType, Option<Piece>)> {
+    ) -> Result<(), GameError> {
+        let piece = self
+            .piece_at(from)
+            .filter(|piece| piece.color == player_color)
+            .ok_or(GameError::NoPieceAtSquare { square: from })?;
+        let target = self.piece_at(to);
+        let capturing = target.is_some_and(|target| target.color != player_color);
+        let own_target = target.is_some_and(|target| target.color == player_color);
+        if !to.is_on_board() || own_target || !piece.can_reach(to, capturing) {
+            return Err(GameError::IllegalMove {
+                piece: piece.piece_type,
+                from,
+                to,
+            });
+        }
         if !self.is_path_clear(from, to) {
-            bail!("The path is currently occupied");
+            return Err(GameError::PathBlocked { from, to });
+        }
+        let mut board_after = self.clone();
+        board_after.apply_move(from, to);
+        if let Some(king) = board_after.find_king(player_color) {
+            if board_after.is_attacked(king.position, player_color.invert()) {
+                return Err(GameError::KingInCheck {
+                    king: king.position,
+                });
+            }
         }
-        let piece = self.find_own_piece_at_mut(from);
-        let piece = piece.ok_or(anyhow!("You don't have a piece at position {from:?}"))?;
+        Ok(())
+    }
+
+    pub fn has_legal_move(&self, color: PieceColor) -> bool {
+        let squares = (0..8).flat_map(|column| (0..8).map(move |row| Position::new(column, row)));
+        let squares = squares.collect::<Vec<_>>();
+        self.pieces
+            .iter()
+            .filter(|piece| piece.color == color)
+            .any(|piece| {
+                squares
+                    .iter()
+                    .any(|square| self.check_move(color, piece.position, *square).is_ok())
+            })
+    }
+
+    fn apply_move(&mut self, from: Position, to: Position) -> (PieceType, Option<Piece>) {
+        let removed = self
+            .pieces
+            .iter()
+            .position(|piece| piece.position == to)
+            .map(|index| self.pieces.swap_remove(index));
+        let piece = self
+            .pieces
+            .iter_mut()
+            .find(|piece| piece.position == from)
+            .expect("the move was checked");
         let piece_type = piece.piece_type;
-        piece.move_piece_to(to).await?;
-        let removed = self.remove_piece(to, player_color.invert());
-        Ok((piece_type, removed))
+        piece.move_to(to);
+        (piece_type, removed)
+    }
+
+    /// Makes the move if the rules allow it.
+    /// Returns the type of the moved piece and the captured piece, if any.
+    pub fn move_piece(
+        &mut self,
+        player_color: PieceColor,
+        from: Position,
+        to: Position,
+    ) -> Result<(PieceType, Option<Piece>), GameError> {
+        self.check_move(player_color, from, to)?;
+        Ok(self.apply_move(from, to))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Square in algebraic notation, such as "e4"
+    fn square(name: &str) -> Position {
+        let [column, row] = name.as_bytes() else {
+            panic!("Not a square: {name}");
+        };
+        Position::new((column - b'a') as i8, (row - b'1') as i8)
+    }
+
+    fn board(pieces: &[(PieceType, PieceColor, &str)]) -> ChessBoard {
+        let pieces = pieces
+            .iter()
+            .map(|&(piece_type, color, name)| Piece {
+                piece_type,
+                color,
+                position: square(name),
+                times_moved: 1,
+            })
+            .collect();
+        ChessBoard { pieces }
+    }
+
+    fn check(board: &ChessBoard, from: &str, to: &str) -> Result<(), GameError> {
+        board.check_move(PieceColor::White, square(from), square(to))
+    }
+
+    #[test]
+    fn pinned_piece_cant_move() {
+        let board = board(&[
+            (PieceType::King, PieceColor::White, "e1"),
+            (PieceType::Rook, PieceColor::White, "e2"),
+            (PieceType::Rook, PieceColor::Black, "e8"),
+            (PieceType::King, PieceColor::Black, "h8"),
+        ]);
+        assert!(matches!(
+            check(&board, "e2", "d2"),
+            Err(GameError::KingInCheck { .. })
+        ));
+        // Moving along the pin keeps the king covered
+        assert!(check(&board, "e2", "e5").is_ok());
+    }
+
+    #[test]
+    fn king_cant_move_into_check() {
+        let board = board(&[
+            (PieceType::King, PieceColor::White, "e1"),
+            (PieceType::Rook, PieceColor::Black, "d8"),
+            (PieceType::King, PieceColor::Black, "h8"),
+        ]);
+        assert!(matches!(
+            check(&board, "e1", "d1"),
+            Err(GameError::KingInCheck { .. })
+        ));
+        assert!(check(&board, "e1", "f1").is_ok());
+    }
+
+    #[test]
+    fn move_leaving_the_king_in_check_is_rejected() {
+        let board = board(&[
+            (PieceType::King, PieceColor::White, "e1"),
+            (PieceType::Knight, PieceColor::White, "b1"),
+            (PieceType::Bishop, PieceColor::White, "c4"),
+            (PieceType::Rook, PieceColor::Black, "e8"),
+            (PieceType::King, PieceColor::Black, "h8"),
+        ]);
+        assert!(board.is_in_check(PieceColor::White));
+        assert!(matches!(
+            check(&board, "b1", "c3"),
+            Err(GameError::KingInCheck { .. })
+        ));
+        // Blocking the rook gets the king out of check
+        assert!(check(&board, "c4", "e2").is_ok());
+    }
+
+    #[test]
+    fn back_rank_checkmate() {
+        let board = board(&[
+            (PieceType::King, PieceColor::White, "g1"),
+            (PieceType::Pawn, PieceColor::White, "f2"),
+            (PieceType::Pawn, PieceColor::White, "g2"),
+            (PieceType::Pawn, PieceColor::White, "h2"),
+            (PieceType::Rook, PieceColor::Black, "a1"),
+            (PieceType::King, PieceColor::Black, "g8"),
+        ]);
+        assert!(board.is_in_check(PieceColor::White));
+        assert!(!board.has_legal_move(PieceColor::White));
+    }
+
+    #[test]
+    fn cornered_king_is_stalemated() {
+        let board = board(&[
+            (PieceType::King, PieceColor::White, "h1"),
+            (PieceType::Queen, PieceColor::White, "c7"),
+            (PieceType::King, PieceColor::Black, "a8"),
+        ]);
+        assert!(!board.is_in_check(PieceColor::Black));
+        assert!(!board.has_legal_move(PieceColor::Black));
+        assert!(board.has_legal_move(PieceColor::White));
+    }
+
+    #[test]
+    fn king_cant_castle_through_an_attacked_square() {
+        let board = board(&[
+            (PieceType::King, PieceColor::White, "e1"),
+            (PieceType::Rook, PieceColor::White, "h1"),
+            (PieceType::Rook, PieceColor::Black, "f8"),
+            (PieceType::King, PieceColor::Black, "a8"),
+        ]);
+        // The chessboard doesn't know castling, so the king can't get past f1 either way
+        assert!(check(&board, "e1", "g1").is_err());
+        assert!(matches!(
+            check(&board, "e1", "f1"),
+            Err(GameError::KingInCheck { .. })
+        ));
     }
 }
@@ -0,0 +1,66 @@
+use std::time::Duration;
+
+use tokio::time::Instant;
+
+use crate::routes::game::{game_type::TimeControl, piece_color::PieceColor};
+
+#[derive(Debug)]
+pub struct ChessClock {
+    white: Duration,
+    black: Duration,
+    increment: Duration,
+    /// The side whose time is running and since when
+    running: Option<(PieceColor, Instant)>,
+}
+
+impl ChessClock {
+    pub fn new(time_control: TimeControl) -> Self {
+        Self {
+            white: time_control.initial(),
+            black: time_control.initial(),
+            increment: time_control.increment(),
+            running: None,
+        }
+    }
+
+    fn stored(&self, color: PieceColor) -> Duration {
+        match color {
+            PieceColor::White => self.white,
+            PieceColor::Black => self.black,
+        }
+    }
+
+    /// Time the player has left, including their running turn
+    pub fn remaining(&self, color: PieceColor) -> Duration {
+        match self.running {
+            Some((running, since)) if running == color => {
+                self.stored(color).saturating_sub(since.elapsed())
+            }
+            _ => self.stored(color),
+        }
+    }
+
+    pub fn remaining_ms(&self, color: PieceColor) -> u64 {
+        self.remaining(color).as_millis() as u64
+    }
+
+    pub fn start(&mut self, color: PieceColor) {
+        self.running = Some((color, Instant::now()));
+    }
+
+    /// Stops the player's time after their move and adds the increment
+    pub fn punch(&mut self, color: PieceColor) {
+        let remaining = self.remaining(color) + self.increment;
+        self.running = None;
+        match color {
+            PieceColor::White => self.white = remaining,
+            PieceColor::Black => self.black = remaining,
+        }
+    }
+
+    /// When the side whose time is running runs out of it
+    pub fn flag_deadline(&self) -> Option<(PieceColor, Instant)> {
+        let (color, since) = self.running?;
+        Some((color, since + self.stored(color)))
+    }
+}
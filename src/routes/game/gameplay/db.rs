@@ -1,11 +1,18 @@
 use anyhow::anyhow;
 use chrono::Utc;
 use serde::{Deserialize, Serialize};
-use sqlx::{Pool, Postgres};
+use sqlx::{PgConnection, Pool, Postgres};
 
 use crate::routes::game::{matchmaking::db::Game, piece_color::PieceColor};
 
-use super::{piece::PieceType, player::GamePlayer, position::Position};
+use super::{
+    chat::ChatLine,
+    outcome::{GameEndReason, GameResult, GameStatus},
+    piece::PieceType,
+    player::GamePlayer,
+    position::Position,
+    rating::Rating,
+};
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct GameTurn {
@@ -50,30 +57,163 @@ impl GameTurn {
     }
 }
 
+pub async fn save_chat_line(
+    db_pool: &Pool<Postgres>,
+    game: &Game,
+    line: &ChatLine,
+) -> anyhow::Result<sqlx::postgres::PgQueryResult> {
+    Ok(sqlx::query!(
+        "INSERT INTO game_chat (game, player, room, message, sent_at) VALUES ($1, $2, $3, $4, $5)",
+        game.id,
+        line.author,
+        line.room.get_name(),
+        line.text,
+        Utc::now().naive_utc()
+    )
+    .execute(db_pool)
+    .await?)
+}
+
 pub async fn set_game_finished(
+    db: &mut PgConnection,
+    game: &Game,
+    winner: Option<&GamePlayer>,
+    result: Option<GameResult>,
+    termination: GameEndReason,
+    status: GameStatus,
+    status_reason: Option<String>,
+) -> anyhow::Result<Game> {
+    Ok(sqlx::query_as!(
+        Game,
+        "UPDATE game SET ended_at = $1, winner = $2, result = $3, termination = $4, status = $5, status_reason = $6 WHERE id = $7 RETURNING *",
+        Utc::now().naive_utc(),
+        winner.map(|winner| winner.id),
+        result.map(|result| result.get_name()),
+        termination.get_name(),
+        status.get_name(),
+        status_reason,
+        game.id
+    )
+    .fetch_one(db)
+    .await?)
+}
+
+pub async fn set_game_errored(
     db_pool: &Pool<Postgres>,
     game: &Game,
-    winner: &GamePlayer,
+    error: &anyhow::Error,
 ) -> anyhow::Result<Game> {
     Ok(sqlx::query_as!(
         Game,
-        "UPDATE game SET ended_at = $1, winner = $2 WHERE id = $3 RETURNING *",
+        "UPDATE game SET ended_at = $1, status = $2, status_reason = $3 WHERE id = $4 RETURNING *",
         Utc::now().naive_utc(),
-        winner.id,
+        GameStatus::Errored.get_name(),
+        error.to_string(),
         game.id
     )
     .fetch_one(db_pool)
     .await?)
 }
 
-pub async fn increase_winner_score(
-    db: &Pool<Postgres>,
-    player: &GamePlayer,
-) -> anyhow::Result<sqlx::postgres::PgQueryResult> {
-    Ok(sqlx::query!(
-        "UPDATE player SET score = score + 1 WHERE id = $1",
-        player.id
+pub async fn set_game_aborted(
+    db_pool: &Pool<Postgres>,
+    game: &Game,
+    reason: &str,
+) -> anyhow::Result<Game> {
+    Ok(sqlx::query_as!(
+        Game,
+        "UPDATE game SET ended_at = $1, status = $2, status_reason = $3 WHERE id = $4 RETURNING *",
+        Utc::now().naive_utc(),
+        GameStatus::Aborted.get_name(),
+        reason,
+        game.id
     )
-    .execute(db)
+    .fetch_one(db_pool)
     .await?)
 }
+
+/// The player's rating in a pool, missing if they haven't played in it yet
+struct PlayerRating {
+    id: i32,
+    rating: Option<f64>,
+    deviation: Option<f64>,
+    volatility: Option<f64>,
+}
+
+impl From<&PlayerRating> for Rating {
+    fn from(player: &PlayerRating) -> Self {
+        match (player.rating, player.deviation, player.volatility) {
+            (Some(rating), Some(deviation), Some(volatility)) => Rating {
+                rating,
+                deviation,
+                volatility,
+            },
+            _ => Rating::default(),
+        }
+    }
+}
+
+/// Rates both players of the game and records the change in their rating history
+pub async fn update_ratings(
+    db: &mut PgConnection,
+    game: &Game,
+    result: GameResult,
+) -> anyhow::Result<()> {
+    let rating_pool = game.game_type().rating_pool();
+    // Locked in the order of ids so that concurrent updates can't deadlock
+    let players = sqlx::query_as!(
+        PlayerRating,
+        r#"SELECT p.id, r.rating AS "rating?", r.rating_deviation AS "deviation?", r.rating_volatility AS "volatility?" FROM player p LEFT JOIN player_rating r ON r.player = p.id AND r.pool = $3 WHERE p.id = $1 OR p.id = $2 ORDER BY p.id FOR UPDATE OF p"#,
+        game.player_white,
+        game.player_black,
+        rating_pool
+    )
+    .fetch_all(&mut *db)
+    .await?;
+    let find_player = |id: i32| {
+        players
+            .iter()
+            .find(|player| player.id == id)
+            .ok_or(anyhow!("Player {id} doesn't exist"))
+    };
+    let white = find_player(game.player_white)?;
+    let black = find_player(game.player_black)?;
+    let white_score = result.white_score();
+    let updates = [
+        (
+            white,
+            Rating::from(white).updated(&black.into(), white_score),
+        ),
+        (
+            black,
+            Rating::from(black).updated(&white.into(), 1.0 - white_score),
+        ),
+    ];
+    let recorded_at = Utc::now().naive_utc();
+    for (player, new_rating) in updates {
+        sqlx::query!(
+            "INSERT INTO player_rating (player, pool, rating, rating_deviation, rating_volatility) VALUES ($1, $2, $3, $4, $5) ON CONFLICT (player, pool) DO UPDATE SET rating = EXCLUDED.rating, rating_deviation = EXCLUDED.rating_deviation, rating_volatility = EXCLUDED.rating_volatility",
+            player.id,
+            rating_pool,
+            new_rating.rating,
+            new_rating.deviation,
+            new_rating.volatility
+        )
+        .execute(&mut *db)
+        .await?;
+        sqlx::query!(
+            "INSERT INTO rating_history (player, game, pool, rating, rating_deviation, rating_volatility, rating_change, recorded_at) VALUES ($1, $2, $3, $4, $5, $6, $7, $8)",
+            player.id,
+            game.id,
+            rating_pool,
+            new_rating.rating,
+            new_rating.deviation,
+            new_rating.volatility,
+            new_rating.rating - Rating::from(player).rating,
+            recorded_at
+        )
+        .execute(&mut *db)
+        .await?;
+    }
+    Ok(())
+}
@@ -0,0 +1,80 @@
+use crate::routes::game::piece_color::PieceColor;
+
+use super::error::GameError;
+
+/// Minimal number of turns between two draw offers of the same player
+const DRAW_OFFER_INTERVAL: i32 = 4;
+
+#[derive(Clone, Copy, Debug)]
+pub struct DrawOffer {
+    pub color: PieceColor,
+    pub turn_number: i32,
+}
+
+#[derive(Default, Debug)]
+pub struct DrawOffers {
+    pending: Option<DrawOffer>,
+    last_white_offer: Option<i32>,
+    last_black_offer: Option<i32>,
+}
+
+impl DrawOffers {
+    pub fn pending(&self) -> Option<DrawOffer> {
+        self.pending
+    }
+
+    fn last_offer_mut(&mut self, color: PieceColor) -> &mut Option<i32> {
+        match color {
+            PieceColor::White => &mut self.last_white_offer,
+            PieceColor::Black => &mut self.last_black_offer,
+        }
+    }
+
+    pub fn offer(&mut self, color: PieceColor, turn_number: i32) -> Result<(), GameError> {
+        if self.pending.is_some() {
+            return Err(GameError::DrawOfferPending);
+        }
+        let last_offer = self.last_offer_mut(color);
+        if let Some(last_turn) = *last_offer {
+            if turn_number - last_turn < DRAW_OFFER_INTERVAL {
+                return Err(GameError::DrawOfferTooSoon {
+                    next_offer_turn: last_turn + DRAW_OFFER_INTERVAL,
+                });
+            }
+        }
+        *last_offer = Some(turn_number);
+        self.pending = Some(DrawOffer { color, turn_number });
+        Ok(())
+    }
+
+    /// Takes the pending offer made by the opponent of `color`
+    fn take_opponent_offer(&mut self, color: PieceColor) -> Result<DrawOffer, GameError> {
+        match self.pending {
+            Some(offer) if offer.color != color => {
+                self.pending = None;
+                Ok(offer)
+            }
+            Some(_) => Err(GameError::OwnDrawOffer),
+            None => Err(GameError::NoDrawOffer),
+        }
+    }
+
+    pub fn accept(&mut self, color: PieceColor) -> Result<DrawOffer, GameError> {
+        self.take_opponent_offer(color)
+    }
+
+    pub fn decline(&mut self, color: PieceColor) -> Result<DrawOffer, GameError> {
+        self.take_opponent_offer(color)
+    }
+
+    /// Lapses the pending offer once the offering side moves on a later turn.
+    /// Returns the lapsed offer, if any.
+    pub fn on_move(&mut self, color: PieceColor, turn_number: i32) -> Option<DrawOffer> {
+        let offer = self.pending?;
+        if offer.color != color || offer.turn_number >= turn_number {
+            return None;
+        }
+        self.pending = None;
+        Some(offer)
+    }
+}
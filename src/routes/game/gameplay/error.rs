@@ -0,0 +1,128 @@
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+use crate::routes::game::piece_color::PieceColor;
+
+use super::{piece::PieceType, position::Position};
+
+/// Reason for refusing a player's message, identified by a stable `code`
+#[derive(PartialEq, Clone, Serialize, Deserialize, Debug)]
+#[serde(tag = "code", rename_all = "snake_case")]
+pub enum GameError {
+    /// The piece can't move like that
+    IllegalMove {
+        piece: PieceType,
+        from: Position,
+        to: Position,
+    },
+    NotYourTurn,
+    NoPieceAtSquare {
+        square: Position,
+    },
+    /// Another piece stands between the squares
+    PathBlocked {
+        from: Position,
+        to: Position,
+    },
+    /// The move would leave the player's king attacked
+    KingInCheck {
+        king: Position,
+    },
+    DrawOfferPending,
+    /// The player may offer a draw again on the given turn
+    DrawOfferTooSoon {
+        next_offer_turn: i32,
+    },
+    NoDrawOffer,
+    OwnDrawOffer,
+    EmptyChatMessage,
+    ChatMessageTooLong {
+        max_length: usize,
+    },
+    ChatRateLimited,
+    /// Rematches can only be agreed on once the game is over
+    GameInProgress,
+    /// The message isn't one the game understands
+    InvalidMessage {
+        reason: String,
+    },
+    /// Anything on the server's side that the player can't do anything about
+    Internal {
+        message: String,
+    },
+}
+
+impl GameError {
+    /// The error as seen from the side of the player
+    pub fn maybe_invert(&self, color: PieceColor) -> Self {
+        let invert = |position: Position| match color {
+            PieceColor::Black => position.invert(),
+            PieceColor::White => position,
+        };
+        match self.clone() {
+            GameError::IllegalMove { piece, from, to } => GameError::IllegalMove {
+                piece,
+                from: invert(from),
+                to: invert(to),
+            },
+            GameError::NoPieceAtSquare { square } => GameError::NoPieceAtSquare {
+                square: invert(square),
+            },
+            GameError::PathBlocked { from, to } => GameError::PathBlocked {
+                from: invert(from),
+                to: invert(to),
+            },
+            GameError::KingInCheck { king } => GameError::KingInCheck { king: invert(king) },
+            error => error,
+        }
+    }
+}
+
+impl From<&anyhow::Error> for GameError {
+    fn from(error: &anyhow::Error) -> Self {
+        match error.downcast_ref::<GameError>() {
+            Some(game_error) => game_error.clone(),
+            None => GameError::Internal {
+                message: error.to_string(),
+            },
+        }
+    }
+}
+
+impl fmt::Display for GameError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GameError::IllegalMove { piece, from, to } => {
+                write!(f, "The {} can't move from {from} to {to}", piece.get_name())
+            }
+            GameError::NotYourTurn => write!(f, "It's not your turn"),
+            GameError::NoPieceAtSquare { square } => {
+                write!(f, "You don't have a piece at {square}")
+            }
+            GameError::PathBlocked { from, to } => {
+                write!(f, "The path from {from} to {to} is blocked")
+            }
+            GameError::KingInCheck { king } => {
+                write!(f, "The move would leave your king at {king} in check")
+            }
+            GameError::DrawOfferPending => write!(f, "A draw offer is already pending"),
+            GameError::DrawOfferTooSoon { next_offer_turn } => write!(
+                f,
+                "You have offered a draw too recently, wait until turn {next_offer_turn}"
+            ),
+            GameError::NoDrawOffer => write!(f, "There is no draw offer to answer"),
+            GameError::OwnDrawOffer => write!(f, "You can't answer your own draw offer"),
+            GameError::EmptyChatMessage => write!(f, "The message is empty"),
+            GameError::ChatMessageTooLong { max_length } => {
+                write!(f, "The message is longer than {max_length} characters")
+            }
+            GameError::ChatRateLimited => write!(f, "You are sending messages too quickly"),
+            GameError::GameInProgress => write!(f, "The game is still in progress"),
+            GameError::InvalidMessage { reason } => write!(f, "Invalid message: {reason}"),
+            GameError::Internal { message } => write!(f, "{message}"),
+        }
+    }
+}
+
+impl std::error::Error for GameError {}
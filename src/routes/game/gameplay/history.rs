@@ -0,0 +1,37 @@
+use super::ws_message::{GameEvent, GameServerMsg};
+
+/// 32-bit FNV-1a hash of the FEN piece placement.
+/// Clients compute it on their own board to check that it matches the server's.
+pub fn position_hash(fen_placement: &str) -> u32 {
+    fen_placement.bytes().fold(0x811c9dc5, |hash, byte| {
+        (hash ^ u32::from(byte)).wrapping_mul(0x01000193)
+    })
+}
+
+/// Every game message sent to a player, in order
+#[derive(Default, Debug)]
+pub struct MessageHistory {
+    events: Vec<GameEvent>,
+}
+
+impl MessageHistory {
+    pub fn record(&mut self, position_hash: u32, msg: GameServerMsg) -> GameEvent {
+        let event = GameEvent {
+            seq: self.events.len() as u64 + 1,
+            position_hash,
+            msg,
+        };
+        self.events.push(event.clone());
+        event
+    }
+
+    pub fn last_seq(&self) -> u64 {
+        self.events.len() as u64
+    }
+
+    /// Events the player has not seen if the last one they got was `seq`
+    pub fn after(&self, seq: u64) -> &[GameEvent] {
+        let seen = (seq as usize).min(self.events.len());
+        &self.events[seen..]
+    }
+}
@@ -1,86 +1,416 @@
-use anyhow::bail;
+use std::{collections::HashMap, time::Duration};
+
+use anyhow::{anyhow, bail};
 use axum::extract::ws::Message;
+use chat::{ChatLimiter, ChatLine, ChatRoom};
 use chessboard::ChessBoard;
-use db::{increase_winner_score, set_game_finished, GameTurn};
+use clock::ChessClock;
+use db::{
+    save_chat_line, set_game_aborted, set_game_errored, set_game_finished, update_ratings, GameTurn,
+};
+use draw_offer::DrawOffers;
+use error::GameError;
+use history::{position_hash, MessageHistory};
+use outcome::{GameEndReason, GameOutcome, GameResult, GameStatus};
+use piece::{Piece, PieceType};
 use player::GamePlayer;
+use rematch::offer_rematch;
 use sqlx::{Pool, Postgres};
-use ws_message::GameServerMsg;
+use tokio::{
+    sync::mpsc,
+    time::{interval, sleep_until, Instant, Interval},
+};
+use ws_message::{GameEvent, GameServerMsg, GameSnapshot};
 
-use super::matchmaking::db::Game;
+use super::game_registry::{GameCommand, GameLink, GameRegistry, LiveGame};
+use super::game_type::GameType;
+use super::matchmaking::{
+    db::{Game, PlayerSummary},
+    ws_message::MatchmakingServerMsg,
+};
 use super::opponent_pair::OpponentPair;
 
 use super::piece_color::PieceColor;
-use super::ws::GameWs;
+use super::ws::{heartbeat_timeout, GameWs, HEARTBEAT_INTERVAL};
 use super::ws_messages::{ChessMove, GameClientMsg, ServerMsg};
 
+pub mod chat;
 pub mod chessboard;
+pub mod clock;
 pub mod db;
+pub mod draw_offer;
+pub mod error;
+pub mod history;
+pub mod outcome;
 pub mod piece;
 pub mod player;
 pub mod position;
+pub mod rating;
+pub mod rematch;
 pub mod ws_message;
 
+/// How long a disconnected player may take to come back before the game is abandoned
+const RECONNECT_GRACE_PERIOD: Duration = Duration::from_secs(60);
+
+enum SessionEvent {
+    Command(GameCommand),
+    Disconnected(PieceColor),
+    Abandoned(PieceColor),
+    HeartbeatCheck,
+    /// The player's time ran out
+    Flagged(PieceColor),
+    /// The player sent something that isn't a game message
+    InvalidMessage(PieceColor, String),
+}
+
 #[derive(Debug)]
 pub struct Gameplay {
     db_pool: Pool<Postgres>,
     pub game_data: Game,
+    game_type: GameType,
     chess_board: ChessBoard,
+    clock: ChessClock,
     pub players: OpponentPair,
     turn_number: i32,
+    /// Half-moves since the last capture or pawn move
+    halfmove_clock: i32,
+    moves: Vec<ChessMove>,
+    captured: Vec<Piece>,
+    /// How many times each position came up since the last capture or pawn move
+    positions: HashMap<String, u8>,
+    draw_offers: DrawOffers,
+    link: GameLink,
+    spectators: Vec<mpsc::Sender<ServerMsg>>,
+    spectator_history: MessageHistory,
+    chat_limiter: ChatLimiter,
+    heartbeat_check: Interval,
 }
 
 impl Gameplay {
-    async fn ws_next(player: &GameWs) -> anyhow::Result<GameClientMsg> {
-        let ws_message = player.get().await?;
+    /// Returns `None` once the connection has been closed
+    async fn ws_next(player: &GameWs) -> anyhow::Result<Option<GameClientMsg>> {
+        let ws_message = match player.get().await {
+            Ok(Message::Close(_)) | Err(_) => return Ok(None),
+            Ok(ws_message) => ws_message,
+        };
         let Message::Text(message_text) = ws_message else {
             bail!("Incorrect WebSocket message type");
         };
-        Ok(serde_json::from_str::<GameClientMsg>(&message_text)?)
+        Ok(Some(serde_json::from_str::<GameClientMsg>(&message_text)?))
     }
 
-    async fn ws_send(player: &GameWs, msg: GameServerMsg) -> anyhow::Result<()> {
-        player
-            .send(Message::Text(serde_json::to_string(&ServerMsg::Game(msg))?))
-            .await
+    async fn ws_send(
+        player: &mut GamePlayer,
+        position_hash: u32,
+        msg: GameServerMsg,
+    ) -> anyhow::Result<()> {
+        let event = player.history.record(position_hash, msg);
+        // The player can catch up on the history once they reconnect
+        if !player.is_connected() {
+            return Ok(());
+        }
+        let message = Message::Text(serde_json::to_string(&ServerMsg::Game(event.clone()))?);
+        // A failed send means the connection is going away, which the reading side notices
+        let _ = player.ws.send(message).await;
+        Ok(())
+    }
+
+    /// Registers the game and runs it in the background until it ends
+    pub async fn spawn(
+        db_pool: Pool<Postgres>,
+        game_registry: GameRegistry,
+        game_data: Game,
+        players: OpponentPair,
+        [white, black]: [PlayerSummary; 2],
+    ) {
+        let game_type = game_data.game_type();
+        let link = game_registry
+            .register(LiveGame::new(game_data.id, game_type, white, black))
+            .await;
+        let (game_id, game_record) = (game_data.id, game_data.clone());
+        let game = Self::new(db_pool.clone(), game_data, players, link);
+        let session = tokio::spawn(game.session(game_registry.clone()));
+        // A game that panicked still has to let its players go
+        tokio::spawn(async move {
+            if session.await.is_err() && game_registry.unregister(game_id).await {
+                let error = anyhow!("The game stopped unexpectedly");
+                let _ = set_game_errored(&db_pool, &game_record, &error).await;
+            }
+        });
+    }
+
+    async fn session(mut self, game_registry: GameRegistry) {
+        // Start the game :D
+        let game_result = self.run().await;
+        game_registry.unregister(self.game_data.id).await;
+        // Check for errors
+        match game_result {
+            Ok(Some(_)) => (),
+            // Aborted games were never really played, so there is nothing to rematch
+            Ok(None) => return,
+            Err(error) => {
+                // The game stays in the database for the record.
+                // We still want to panic on database errors though
+                set_game_errored(&self.db_pool, &self.game_data, &error)
+                    .await
+                    .unwrap();
+                // Game has encountered an error. Notify the active players.
+                let error =
+                    ServerMsg::Matchmaking(MatchmakingServerMsg::GameDropped(error.to_string()));
+                // This operation will probably foil for one of them, so we ignore the errors, as this is an error handler.
+                let _ = self.players.white_player.ws.send_as_text(&error).await;
+                let _ = self.players.black_player.ws.send_as_text(&error).await;
+                return;
+            }
+        }
+        let Gameplay {
+            db_pool,
+            game_data,
+            players,
+            spectators,
+            link,
+            ..
+        } = self;
+        // Spectators and the featured stream move on while the players decide on a rematch
+        drop(spectators);
+        drop(link);
+        offer_rematch(db_pool, game_registry, game_data, players).await;
     }
 
-    pub fn new(db_pool: Pool<Postgres>, game_data: Game, players: OpponentPair) -> Self {
+    pub fn new(
+        db_pool: Pool<Postgres>,
+        game_data: Game,
+        players: OpponentPair,
+        link: GameLink,
+    ) -> Self {
+        let game_type = game_data.game_type();
+        let chess_board = ChessBoard::new(game_type.variant);
+        let start_position = chess_board.fen_placement() + " w";
         Self {
             db_pool,
             game_data,
-            chess_board: ChessBoard::new(),
+            game_type,
+            chess_board,
+            clock: ChessClock::new(game_type.time_control),
             players,
             turn_number: 1,
+            halfmove_clock: 0,
+            moves: Vec::new(),
+            captured: Vec::new(),
+            positions: HashMap::from([(start_position, 1)]),
+            draw_offers: DrawOffers::default(),
+            link,
+            spectators: Vec::new(),
+            spectator_history: MessageHistory::default(),
+            chat_limiter: ChatLimiter::default(),
+            heartbeat_check: interval(HEARTBEAT_INTERVAL),
         }
     }
 
     async fn ws_send_active(&mut self, msg: GameServerMsg) -> anyhow::Result<()> {
-        Self::ws_send(&self.players.get_active().ws, msg).await
+        self.ws_send_to(self.players.current_player_color, msg)
+            .await
     }
 
     async fn ws_send_passive(&mut self, msg: GameServerMsg) -> anyhow::Result<()> {
-        Self::ws_send(&self.players.get_passive().ws, msg).await
+        self.ws_send_to(self.players.current_player_color.invert(), msg)
+            .await
     }
 
-    async fn ws_next_active(&mut self) -> anyhow::Result<GameClientMsg> {
-        Self::ws_next(&self.players.get_active().ws).await
+    /// Sends the message to both players and all spectators
+    async fn ws_send_all(&mut self, msg: GameServerMsg) -> anyhow::Result<()> {
+        self.ws_send_to(PieceColor::White, msg.clone()).await?;
+        self.ws_send_to(PieceColor::Black, msg.clone()).await?;
+        self.ws_send_spectators(msg);
+        Ok(())
+    }
+
+    fn ws_send_spectators(&mut self, msg: GameServerMsg) {
+        let position_hash = position_hash(&self.chess_board.fen_placement());
+        let event = self.spectator_history.record(position_hash, msg);
+        // Spectators who left or can't keep up are dropped
+        self.spectators
+            .retain(|spectator| spectator.try_send(ServerMsg::Game(event.clone())).is_ok());
+        self.update_spectator_count();
+    }
+
+    fn update_spectator_count(&mut self) {
+        let spectator_count = self.spectators.len();
+        self.link.live_game.send_if_modified(|live_game| {
+            let modified = live_game.spectator_count != spectator_count;
+            live_game.spectator_count = spectator_count;
+            modified
+        });
     }
 
-    async fn ws_next_passive(&mut self) -> anyhow::Result<GameClientMsg> {
-        Self::ws_next(&self.players.get_passive().ws).await
+    async fn ws_send_to(&mut self, color: PieceColor, msg: GameServerMsg) -> anyhow::Result<()> {
+        let position_hash = position_hash(&self.chess_board.fen_placement());
+        Self::ws_send(self.players.get_by_color_mut(color), position_hash, msg).await
+    }
+
+    /// Sends the player again every message that came after `seq`
+    async fn resync(&self, color: PieceColor, seq: u64) -> anyhow::Result<()> {
+        let player = self.players.get_by_color(color);
+        for event in player.history.after(seq) {
+            player
+                .ws
+                .send_as_text(&ServerMsg::Game(event.clone()))
+                .await?;
+        }
+        Ok(())
+    }
+
+    fn message_event(
+        &self,
+        color: PieceColor,
+        message: anyhow::Result<Option<GameClientMsg>>,
+    ) -> SessionEvent {
+        match message {
+            Ok(Some(msg)) => SessionEvent::Command(GameCommand::Play {
+                player_id: self.players.get_by_color(color).id,
+                msg,
+            }),
+            Ok(None) => SessionEvent::Disconnected(color),
+            Err(error) => SessionEvent::InvalidMessage(color, error.to_string()),
+        }
+    }
+
+    /// Waits for a message from whichever player sends one first,
+    /// or for a change in the players' connections
+    async fn next_event(&mut self) -> anyhow::Result<SessionEvent> {
+        let white = &self.players.white_player;
+        let black = &self.players.black_player;
+        // The player who has been gone the longest runs out of time first
+        let abandoning = [(PieceColor::White, white), (PieceColor::Black, black)]
+            .into_iter()
+            .filter_map(|(color, player)| Some((color, player.disconnected_at?)))
+            .min_by_key(|(_, disconnected_at)| *disconnected_at);
+        let flagging = self.clock.flag_deadline();
+        let flag_deadline = flagging
+            .map(|(_, deadline)| deadline)
+            .unwrap_or_else(Instant::now);
+        let grace_deadline = abandoning
+            .map(|(_, disconnected_at)| disconnected_at + RECONNECT_GRACE_PERIOD)
+            .unwrap_or_else(Instant::now);
+        tokio::select! {
+            message = Self::ws_next(&white.ws), if white.is_connected() => {
+                Ok(self.message_event(PieceColor::White, message))
+            }
+            message = Self::ws_next(&black.ws), if black.is_connected() => {
+                Ok(self.message_event(PieceColor::Black, message))
+            }
+            Some(command) = self.link.commands.recv() => {
+                Ok(SessionEvent::Command(command))
+            }
+            _ = sleep_until(grace_deadline), if abandoning.is_some() => {
+                Ok(SessionEvent::Abandoned(abandoning.unwrap().0))
+            }
+            _ = self.heartbeat_check.tick() => Ok(SessionEvent::HeartbeatCheck),
+            _ = sleep_until(flag_deadline), if flagging.is_some() => {
+                Ok(SessionEvent::Flagged(flagging.unwrap().0))
+            }
+        }
+    }
+
+    /// Records the players' latency and looks for a connected player who went silent
+    async fn check_heartbeats(&mut self) -> Option<PieceColor> {
+        let mut silent_player = None;
+        for color in [PieceColor::White, PieceColor::Black] {
+            let player = self.players.get_by_color_mut(color);
+            if !player.is_connected() {
+                continue;
+            }
+            player.latency = player.ws.heartbeat().await.latency;
+            if !player.ws.is_alive().await {
+                silent_player = silent_player.or(Some(color));
+            }
+        }
+        silent_player
+    }
+
+    async fn handle_disconnect(&mut self, player_color: PieceColor) -> anyhow::Result<()> {
+        self.players.get_by_color_mut(player_color).disconnected_at = Some(Instant::now());
+        self.ws_send_to(player_color.invert(), GameServerMsg::OpponentDisconnected)
+            .await
+    }
+
+    async fn handle_command(&mut self, command: GameCommand) -> anyhow::Result<()> {
+        match command {
+            GameCommand::Reconnect { player_id, ws } => self.handle_reconnect(player_id, ws).await,
+            GameCommand::Spectate(spectator) => {
+                self.handle_spectate(spectator);
+                Ok(())
+            }
+            GameCommand::SpectatorChat {
+                user_id,
+                text,
+                reply,
+            } => {
+                let result = self.handle_chat(ChatRoom::Spectators, user_id, &text).await;
+                let _ = reply.send(result);
+                Ok(())
+            }
+            // These end up in the game loop
+            GameCommand::Play { .. } | GameCommand::Abort { .. } => Ok(()),
+        }
+    }
+
+    async fn handle_chat(&mut self, room: ChatRoom, author: i32, text: &str) -> anyhow::Result<()> {
+        let text = self.chat_limiter.check(author, text)?;
+        let line = ChatLine { room, author, text };
+        save_chat_line(&self.db_pool, &self.game_data, &line).await?;
+        match room {
+            ChatRoom::Players => {
+                self.ws_send_to(PieceColor::White, GameServerMsg::Chat(line.clone()))
+                    .await?;
+                self.ws_send_to(PieceColor::Black, GameServerMsg::Chat(line))
+                    .await
+            }
+            ChatRoom::Spectators => {
+                self.ws_send_spectators(GameServerMsg::Chat(line));
+                Ok(())
+            }
+        }
+    }
+
+    async fn handle_reconnect(&mut self, player_id: i32, ws: GameWs) -> anyhow::Result<()> {
+        let Some(player_color) = self.players.get_color_of(player_id) else {
+            return Ok(());
+        };
+        let player = self.players.get_by_color_mut(player_color);
+        // Replacing a live connection closes the old one
+        player.ws = ws;
+        player.disconnected_at = None;
+        self.ws_send_to(player_color, self.snapshot(Some(player_color)))
+            .await?;
+        self.ws_send_to(player_color.invert(), GameServerMsg::OpponentReconnected)
+            .await
+    }
+
+    fn handle_spectate(&mut self, spectator: mpsc::Sender<ServerMsg>) {
+        // The snapshot reflects every spectator message sent so far
+        let snapshot = GameEvent {
+            seq: self.spectator_history.last_seq(),
+            position_hash: position_hash(&self.chess_board.fen_placement()),
+            msg: self.snapshot(None),
+        };
+        if spectator.try_send(ServerMsg::Game(snapshot)).is_ok() {
+            self.spectators.push(spectator);
+            self.update_spectator_count();
+        }
     }
 
     async fn handle_turn_end(&mut self, piece_move: ChessMove) -> anyhow::Result<()> {
         let player_color = self.players.current_player_color;
         let piece_move = piece_move.maybe_invert(player_color);
-        let (piece_type, removed_piece_maybe) = self
-            .chess_board
-            .move_piece(
-                player_color,
-                piece_move.position_from,
-                piece_move.position_to,
-            )
-            .await?;
+        let (piece_type, removed_piece_maybe) = self.chess_board.move_piece(
+            player_color,
+            piece_move.position_from,
+            piece_move.position_to,
+        )?;
+        // The clocks switch before anything waits on the database or the sockets
+        self.clock.punch(player_color);
+        self.clock.start(player_color.invert());
         GameTurn::create(
             &self.db_pool,
             &self.game_data,
@@ -91,100 +421,364 @@ impl Gameplay {
             piece_type,
         )
         .await?;
-        let removed_piece_to =
-            removed_piece_maybe.map(|piece| (piece.color, piece_move.position_to));
-        self.players
-            .white_player
-            .ws
-            .send_as_text(&ServerMsg::Game(GameServerMsg::PawnMove(
-                piece_move,
-                removed_piece_to,
-            )))
-            .await?;
-        self.players
-            .black_player
-            .ws
-            .send_as_text(&ServerMsg::Game(GameServerMsg::PawnMove(
+        let removed_piece_to = removed_piece_maybe
+            .as_ref()
+            .map(|piece| (piece.color, piece_move.position_to));
+        if piece_type == PieceType::Pawn || removed_piece_maybe.is_some() {
+            self.halfmove_clock = 0;
+        } else {
+            self.halfmove_clock += 1;
+        }
+        self.moves.push(piece_move);
+        let move_count = self.moves.len();
+        self.link
+            .live_game
+            .send_modify(|live_game| live_game.move_count = move_count);
+        self.captured.extend(removed_piece_maybe);
+        self.ws_send_to(
+            PieceColor::White,
+            GameServerMsg::PawnMove(piece_move, removed_piece_to),
+        )
+        .await?;
+        self.ws_send_to(
+            PieceColor::Black,
+            GameServerMsg::PawnMove(
                 piece_move.invert(),
                 removed_piece_to.map(|to| (to.0, to.1.invert())),
-            )))
-            .await?;
+            ),
+        )
+        .await?;
+        self.ws_send_spectators(GameServerMsg::PawnMove(piece_move, removed_piece_to));
+        if let Some(lapsed_offer) = self.draw_offers.on_move(player_color, self.turn_number) {
+            self.ws_send_all(GameServerMsg::DrawOfferLapsed(lapsed_offer.color))
+                .await?;
+        }
         Ok(())
     }
 
+    fn fen(&self) -> String {
+        let side_to_move = match self.players.current_player_color {
+            PieceColor::White => "w",
+            PieceColor::Black => "b",
+        };
+        // Castling and en passant are not supported by the chessboard
+        format!(
+            "{} {side_to_move} - - {} {}",
+            self.chess_board.fen_placement(),
+            self.halfmove_clock,
+            (self.turn_number + 1) / 2
+        )
+    }
+
+    /// Full state of the game, as seen from the side of the given player
+    fn snapshot(&self, color: Option<PieceColor>) -> GameServerMsg {
+        GameServerMsg::State(GameSnapshot {
+            color,
+            game_type: self.game_type,
+            rated: self.game_data.rated,
+            fen: self.fen(),
+            side_to_move: self.players.current_player_color,
+            moves: self
+                .moves
+                .iter()
+                .map(|chess_move| chess_move.maybe_invert(color.unwrap_or(PieceColor::White)))
+                .collect(),
+            captured: self
+                .captured
+                .iter()
+                .map(|piece| (piece.color, piece.piece_type))
+                .collect(),
+            draw_offer: self.draw_offers.pending().map(|offer| offer.color),
+            white_latency_ms: self.players.white_player.latency_ms(),
+            black_latency_ms: self.players.black_player.latency_ms(),
+            white_clock_ms: self.clock.remaining_ms(PieceColor::White),
+            black_clock_ms: self.clock.remaining_ms(PieceColor::Black),
+        })
+    }
+
+    async fn handle_draw_offer(&mut self, player_color: PieceColor) -> anyhow::Result<()> {
+        self.draw_offers.offer(player_color, self.turn_number)?;
+        self.ws_send_all(GameServerMsg::DrawOffered(player_color))
+            .await
+    }
+
+    async fn handle_draw_decline(&mut self, player_color: PieceColor) -> anyhow::Result<()> {
+        self.draw_offers.decline(player_color)?;
+        self.ws_send_all(GameServerMsg::DrawDeclined(player_color))
+            .await
+    }
+
     async fn switch_turns(&mut self) -> anyhow::Result<()> {
         self.players.switch_active();
         let _ = self.ws_send_active(GameServerMsg::NewTurn(true)).await;
         let _ = self.ws_send_passive(GameServerMsg::NewTurn(false)).await;
         self.turn_number += 1;
-        Ok(())
+        self.send_clock().await
+    }
+
+    async fn start_clock(&mut self) -> anyhow::Result<()> {
+        self.clock.start(self.players.current_player_color);
+        self.send_clock().await
+    }
+
+    async fn send_clock(&mut self) -> anyhow::Result<()> {
+        self.ws_send_all(GameServerMsg::Clock {
+            white_ms: self.clock.remaining_ms(PieceColor::White),
+            black_ms: self.clock.remaining_ms(PieceColor::Black),
+        })
+        .await
+    }
+
+    /// Counts the position after a move, returns `true` once it came up three times
+    fn is_threefold_repetition(&mut self) -> bool {
+        if self.halfmove_clock == 0 {
+            // Captures and pawn moves can't be undone, so earlier positions can't repeat
+            self.positions.clear();
+        }
+        let position = self.fen().split(' ').take(2).collect::<Vec<_>>().join(" ");
+        let count = self.positions.entry(position).or_default();
+        *count += 1;
+        *count >= 3
+    }
+
+    /// Ends the game once the opponent of the player who just moved has no legal move left
+    fn handle_win(&self) -> Option<GameOutcome> {
+        let player_color = self.players.current_player_color;
+        let opponent_color = player_color.invert();
+        if self.chess_board.has_legal_move(opponent_color) {
+            return None;
+        }
+        Some(match self.chess_board.is_in_check(opponent_color) {
+            true => GameOutcome::win(player_color, GameEndReason::Checkmate),
+            false => GameOutcome::draw(GameEndReason::Stalemate),
+        })
     }
 
-    async fn handle_win(&self) -> anyhow::Result<Option<&GamePlayer>> {
-        let white_king = self.chess_board.find_king(PieceColor::White);
-        let black_king = self.chess_board.find_king(PieceColor::Black);
-        let winning_king = match (white_king, black_king) {
-            (None, Some(_)) => Some(PieceColor::Black),
-            (Some(_), None) => Some(PieceColor::White),
-            (Some(_), Some(_)) => None,
-            (None, None) => {
-                bail!("There is no king on the field! The game encountered a critical error");
+    /// Records the end of the game, returns its result unless it doesn't count
+    async fn finish(&mut self, outcome: GameOutcome) -> anyhow::Result<Option<GameResult>> {
+        let status = outcome.status(self.moves.len());
+        let winner = outcome.counted_winner(self.moves.len());
+        let result = outcome.result(self.moves.len());
+        let status_reason = match (status, outcome.winner) {
+            (GameStatus::Aborted | GameStatus::Abandoned, Some(winner)) => {
+                Some(format!("The {:?} player left the game", winner.invert()))
             }
+            (GameStatus::Aborted, None) => Some("The game was never started".to_owned()),
+            _ => None,
         };
-        if let Some(winning_color) = winning_king {
-            let (winner, loser) = if self.players.current_player_color == winning_color {
-                (self.players.get_active(), self.players.get_passive())
-            } else {
-                (self.players.get_passive(), self.players.get_active())
-            };
-            Self::ws_send(&winner.ws, GameServerMsg::GameEnd(true)).await?;
-            Self::ws_send(&loser.ws, GameServerMsg::GameEnd(false)).await?;
-            Ok(Some(winner))
-        } else {
-            Ok(None)
+        let winner = winner.map(|color| self.players.get_by_color(color));
+        // The result and the ratings are recorded together before anyone hears of them
+        let mut transaction = self.db_pool.begin().await?;
+        set_game_finished(
+            &mut transaction,
+            &self.game_data,
+            winner,
+            result,
+            outcome.reason,
+            status,
+            status_reason,
+        )
+        .await?;
+        // Unrated games leave the ratings as they are
+        if let Some(result) = result.filter(|_| self.game_data.rated) {
+            update_ratings(&mut transaction, &self.game_data, result).await?;
+        }
+        transaction.commit().await?;
+        match result {
+            Some(result) => {
+                self.ws_send_all(GameServerMsg::GameEnd {
+                    result,
+                    reason: outcome.reason,
+                })
+                .await?
+            }
+            None => self.ws_send_all(GameServerMsg::GameAborted).await?,
         }
+        Ok(result)
     }
 
-    pub async fn run(&mut self) -> anyhow::Result<()> {
-        // Wait for both players to acknowledge their involvement
-        let Ok(GameClientMsg::Ack) = self.ws_next_active().await else {
-            bail!("No white player ack");
-        };
-        if !matches!(self.ws_next_passive().await?, GameClientMsg::Ack) {
-            bail!("No black player ack");
-        };
+    async fn abort(&mut self, reason: String) -> anyhow::Result<Option<GameResult>> {
+        self.ws_send_all(GameServerMsg::GameAborted).await?;
+        set_game_aborted(&self.db_pool, &self.game_data, &reason).await?;
+        Ok(None)
+    }
+
+    /// Plays the game out, returns its result unless it was aborted
+    pub async fn run(&mut self) -> anyhow::Result<Option<GameResult>> {
+        // Wait for both players to acknowledge their involvement,
+        // taking reconnects and other commands in the meantime
+        let ack_deadline = Instant::now() + heartbeat_timeout();
+        let mut acked = Vec::new();
+        while acked.len() < 2 {
+            let event = tokio::select! {
+                event = self.next_event() => event?,
+                _ = sleep_until(ack_deadline) => {
+                    let outcome = match acked.first() {
+                        Some(color) => GameOutcome::win(*color, GameEndReason::Abandonment),
+                        // There is no telling who is to blame
+                        None => GameOutcome::draw(GameEndReason::Abandonment),
+                    };
+                    return self.finish(outcome).await;
+                }
+            };
+            let acking_player = match event {
+                SessionEvent::Command(GameCommand::Play {
+                    player_id,
+                    msg: GameClientMsg::Ack,
+                }) => Some(player_id),
+                SessionEvent::Command(GameCommand::Abort { reason }) => {
+                    return self.abort(reason).await;
+                }
+                // A player who comes back is clearly still involved
+                SessionEvent::Command(GameCommand::Reconnect { player_id, ws }) => {
+                    self.handle_reconnect(player_id, ws).await?;
+                    Some(player_id)
+                }
+                SessionEvent::Command(command) => {
+                    self.handle_command(command).await?;
+                    None
+                }
+                SessionEvent::Disconnected(player_color) => {
+                    self.handle_disconnect(player_color).await?;
+                    None
+                }
+                SessionEvent::Abandoned(player_color) => {
+                    return self
+                        .finish(GameOutcome::win(
+                            player_color.invert(),
+                            GameEndReason::Abandonment,
+                        ))
+                        .await;
+                }
+                SessionEvent::HeartbeatCheck => {
+                    if let Some(player_color) = self.check_heartbeats().await {
+                        return self
+                            .finish(GameOutcome::win(
+                                player_color.invert(),
+                                GameEndReason::Abandonment,
+                            ))
+                            .await;
+                    }
+                    None
+                }
+                // The clocks only start with the first turn
+                SessionEvent::Flagged(_) => None,
+                SessionEvent::InvalidMessage(player_color, reason) => {
+                    self.ws_send_to(
+                        player_color,
+                        GameServerMsg::Error(GameError::InvalidMessage { reason }),
+                    )
+                    .await?;
+                    None
+                }
+            };
+            let acking_color =
+                acking_player.and_then(|player_id| self.players.get_color_of(player_id));
+            if let Some(color) = acking_color.filter(|color| !acked.contains(color)) {
+                acked.push(color);
+            }
+        }
+        for color in [PieceColor::White, PieceColor::Black] {
+            self.ws_send_to(color, self.snapshot(Some(color))).await?;
+        }
         self.ws_send_active(GameServerMsg::NewTurn(true)).await?;
         self.ws_send_passive(GameServerMsg::NewTurn(false)).await?;
-        let winner = loop {
-            match self.ws_next_active().await? {
-                GameClientMsg::TurnEnd(piece_move) => {
-                    if let Err(error) = self.handle_turn_end(piece_move).await {
-                        self.ws_send_active(GameServerMsg::Error(format!("{:?}", error)))
-                            .await?;
+        self.start_clock().await?;
+        let outcome = loop {
+            let (player_color, message) = match self.next_event().await? {
+                SessionEvent::Command(GameCommand::Play { player_id, msg }) => {
+                    let Some(player_color) = self.players.get_color_of(player_id) else {
                         continue;
                     };
+                    (player_color, msg)
                 }
-                GameClientMsg::Ack => {
+                SessionEvent::Command(GameCommand::Abort { reason }) => {
+                    return self.abort(reason).await;
+                }
+                SessionEvent::Command(command) => {
+                    self.handle_command(command).await?;
+                    continue;
+                }
+                SessionEvent::Disconnected(player_color) => {
+                    self.handle_disconnect(player_color).await?;
+                    continue;
+                }
+                SessionEvent::Abandoned(player_color) => {
+                    break GameOutcome::win(player_color.invert(), GameEndReason::Abandonment);
+                }
+                SessionEvent::HeartbeatCheck => {
+                    let Some(player_color) = self.check_heartbeats().await else {
+                        continue;
+                    };
+                    break GameOutcome::win(player_color.invert(), GameEndReason::Abandonment);
+                }
+                SessionEvent::Flagged(player_color) => {
+                    break GameOutcome::win(player_color.invert(), GameEndReason::Timeout);
+                }
+                SessionEvent::InvalidMessage(player_color, reason) => {
+                    self.ws_send_to(
+                        player_color,
+                        GameServerMsg::Error(GameError::InvalidMessage { reason }),
+                    )
+                    .await?;
                     continue;
                 }
             };
-            match self.handle_win().await {
-                Ok(None) => {
-                    self.switch_turns().await?;
+            let result = match message {
+                GameClientMsg::TurnEnd(_) if player_color != self.players.current_player_color => {
+                    Err(GameError::NotYourTurn.into())
                 }
-                Ok(Some(winner)) => {
-                    break winner;
+                GameClientMsg::TurnEnd(_) if self.clock.remaining(player_color).is_zero() => {
+                    // The move came in after the clock ran out
+                    break GameOutcome::win(player_color.invert(), GameEndReason::Timeout);
                 }
-                Err(error) => {
-                    self.ws_send_active(GameServerMsg::Error(format!("{:?}", error)))
+                GameClientMsg::TurnEnd(piece_move) => {
+                    match self.handle_turn_end(piece_move).await {
+                        Ok(()) => {
+                            if let Some(outcome) = self.handle_win() {
+                                break outcome;
+                            }
+                            self.switch_turns().await?;
+                            if self.is_threefold_repetition() {
+                                break GameOutcome::draw(GameEndReason::Repetition);
+                            }
+                            continue;
+                        }
+                        Err(error) => Err(error),
+                    }
+                }
+                GameClientMsg::Ack => {
+                    continue;
+                }
+                GameClientMsg::RequestState => {
+                    self.ws_send_to(player_color, self.snapshot(Some(player_color)))
                         .await?;
+                    continue;
+                }
+                GameClientMsg::Resync(seq) => self.resync(player_color, seq).await,
+                GameClientMsg::Chat(text) => {
+                    let author = self.players.get_by_color(player_color).id;
+                    self.handle_chat(ChatRoom::Players, author, &text).await
+                }
+                GameClientMsg::Resign => {
+                    break GameOutcome::win(player_color.invert(), GameEndReason::Resign);
+                }
+                GameClientMsg::DrawOffer => self.handle_draw_offer(player_color).await,
+                GameClientMsg::DrawAccept => match self.draw_offers.accept(player_color) {
+                    Ok(_) => break GameOutcome::draw(GameEndReason::Agreement),
+                    Err(error) => Err(error.into()),
+                },
+                GameClientMsg::DrawDecline => self.handle_draw_decline(player_color).await,
+                GameClientMsg::Rematch | GameClientMsg::RematchDecline => {
+                    Err(GameError::GameInProgress.into())
                 }
             };
+            if let Err(error) = result {
+                let error = GameError::from(&error).maybe_invert(player_color);
+                self.ws_send_to(player_color, GameServerMsg::Error(error))
+                    .await?;
+            }
         };
-        set_game_finished(&self.db_pool, &self.game_data, winner)
-            .await
-            .unwrap();
-        increase_winner_score(&self.db_pool, winner).await?;
-        Ok(())
+        self.finish(outcome).await
     }
 }
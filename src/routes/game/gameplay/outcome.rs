@@ -0,0 +1,132 @@
+use serde::{Deserialize, Serialize};
+
+use crate::routes::game::piece_color::PieceColor;
+
+/// Games abandoned before this many moves are aborted and don't count
+const MOVES_TO_COUNT_ABANDONMENT: usize = 2;
+
+#[derive(PartialEq, Clone, Copy, Serialize, Deserialize, Debug)]
+pub enum GameStatus {
+    Ongoing,
+    Finished,
+    Aborted,
+    Abandoned,
+    Errored,
+}
+
+impl GameStatus {
+    pub fn get_name<'a>(&self) -> &'a str {
+        match *self {
+            GameStatus::Ongoing => "ongoing",
+            GameStatus::Finished => "finished",
+            GameStatus::Aborted => "aborted",
+            GameStatus::Abandoned => "abandoned",
+            GameStatus::Errored => "errored",
+        }
+    }
+}
+
+#[derive(PartialEq, Clone, Copy, Serialize, Deserialize, Debug)]
+pub enum GameEndReason {
+    Checkmate,
+    Resign,
+    /// The player ran out of time
+    Timeout,
+    /// The player to move has no legal move but isn't in check
+    Stalemate,
+    /// The same position came up for the third time
+    Repetition,
+    Agreement,
+    Abandonment,
+}
+
+impl GameEndReason {
+    pub fn get_name<'a>(&self) -> &'a str {
+        match *self {
+            GameEndReason::Checkmate => "checkmate",
+            GameEndReason::Resign => "resign",
+            GameEndReason::Timeout => "timeout",
+            GameEndReason::Stalemate => "stalemate",
+            GameEndReason::Repetition => "repetition",
+            GameEndReason::Agreement => "agreement",
+            GameEndReason::Abandonment => "abandonment",
+        }
+    }
+}
+
+#[derive(PartialEq, Clone, Copy, Serialize, Deserialize, Debug)]
+pub enum GameResult {
+    WhiteWin,
+    BlackWin,
+    Draw,
+}
+
+impl GameResult {
+    pub fn get_name<'a>(&self) -> &'a str {
+        match *self {
+            GameResult::WhiteWin => "white_win",
+            GameResult::BlackWin => "black_win",
+            GameResult::Draw => "draw",
+        }
+    }
+
+    /// Points scored by white, with black scoring the rest of one
+    pub fn white_score(&self) -> f64 {
+        match *self {
+            GameResult::WhiteWin => 1.0,
+            GameResult::BlackWin => 0.0,
+            GameResult::Draw => 0.5,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct GameOutcome {
+    /// `None` when the game ended in a draw
+    pub winner: Option<PieceColor>,
+    pub reason: GameEndReason,
+}
+
+impl GameOutcome {
+    pub fn win(winner: PieceColor, reason: GameEndReason) -> Self {
+        Self {
+            winner: Some(winner),
+            reason,
+        }
+    }
+
+    pub fn draw(reason: GameEndReason) -> Self {
+        Self {
+            winner: None,
+            reason,
+        }
+    }
+
+    pub fn status(&self, moves_made: usize) -> GameStatus {
+        match self.reason {
+            GameEndReason::Abandonment if moves_made < MOVES_TO_COUNT_ABANDONMENT => {
+                GameStatus::Aborted
+            }
+            GameEndReason::Abandonment => GameStatus::Abandoned,
+            _ => GameStatus::Finished,
+        }
+    }
+
+    /// The result, unless the game doesn't count
+    pub fn result(&self, moves_made: usize) -> Option<GameResult> {
+        match (self.status(moves_made), self.winner) {
+            (GameStatus::Aborted, _) => None,
+            (_, Some(PieceColor::White)) => Some(GameResult::WhiteWin),
+            (_, Some(PieceColor::Black)) => Some(GameResult::BlackWin),
+            (_, None) => Some(GameResult::Draw),
+        }
+    }
+
+    /// The winner, unless the game doesn't count
+    pub fn counted_winner(&self, moves_made: usize) -> Option<PieceColor> {
+        match self.status(moves_made) {
+            GameStatus::Aborted => None,
+            _ => self.winner,
+        }
+    }
+}
@@ -1,4 +1,3 @@
-use anyhow::bail;
 use serde::{Deserialize, Serialize};
 
 use crate::routes::game::piece_color::PieceColor;
@@ -26,6 +25,21 @@ impl PieceType {
             PieceType::Rook => "rook",
         }
     }
+
+    pub fn get_fen_char(&self, color: PieceColor) -> char {
+        let fen_char = match *self {
+            PieceType::Pawn => 'p',
+            PieceType::Bishop => 'b',
+            PieceType::King => 'k',
+            PieceType::Knight => 'n',
+            PieceType::Queen => 'q',
+            PieceType::Rook => 'r',
+        };
+        match color {
+            PieceColor::White => fen_char.to_ascii_uppercase(),
+            PieceColor::Black => fen_char,
+        }
+    }
 }
 
 #[derive(Clone, Serialize, Deserialize, Debug)]
@@ -52,93 +66,41 @@ impl Piece {
         }
     }
 
-    pub async fn move_piece_to(&mut self, new_position: Position) -> anyhow::Result<()> {
-        let position_difference = new_position - self.position;
+    /// Whether the piece's way of moving can take it to the square, ignoring other pieces
+    /// except for whether there is one to capture
+    pub fn can_reach(&self, to: Position, capturing: bool) -> bool {
+        let row_difference = to.row - self.position.row;
+        let (rows, columns) = to - self.position;
         match self.piece_type {
             PieceType::Pawn => {
-                self.pawn_move(new_position, position_difference).await?;
-                match self.color {
-                    PieceColor::White if new_position.row == 7 => {
-                        self.piece_type = PieceType::Queen;
-                    }
-                    PieceColor::Black if new_position.row == 0 => {
-                        self.piece_type = PieceType::Queen;
-                    }
-                    _ => (),
+                let forward = match self.color {
+                    PieceColor::White => 1,
+                    PieceColor::Black => -1,
                 };
-            }
-            PieceType::Knight => {
-                if !((position_difference.0.abs() == 1 && position_difference.1.abs() == 2)
-                    || (position_difference.0.abs() == 2 && position_difference.1.abs() == 1))
-                {
-                    bail!("Incorrect knight move!");
-                }
-            }
-            PieceType::King => {
-                if !(position_difference.0.abs() <= 1 && position_difference.1.abs() <= 1) {
-                    bail!("Incorrect King move!");
-                }
-            }
-            PieceType::Rook => {
-                self.rook_move(position_difference).await?;
-            }
-            PieceType::Bishop => {
-                self.bishop_move(position_difference).await?;
-            }
-            PieceType::Queen => {
-                let move_successful = [
-                    self.rook_move(position_difference).await,
-                    self.bishop_move(position_difference).await,
-                ]
-                .iter()
-                .any(|result| result.is_ok());
-                if !move_successful {
-                    bail!("Incorrect queen move");
+                if columns == 0 && !capturing {
+                    row_difference == forward
+                        || (row_difference == 2 * forward && self.times_moved == 0)
+                } else {
+                    columns == 1 && row_difference == forward && capturing
                 }
             }
-        };
-        self.position = new_position;
-        self.times_moved += 1;
-        Ok(())
-    }
-
-    async fn rook_move(&mut self, position_difference: (i8, i8)) -> Result<(), anyhow::Error> {
-        if position_difference.0 != 0 && position_difference.1 != 0 {
-            bail!("Incorrect rook move");
+            PieceType::Knight => (rows == 1 && columns == 2) || (rows == 2 && columns == 1),
+            PieceType::King => rows <= 1 && columns <= 1 && rows + columns > 0,
+            PieceType::Rook => (rows == 0) != (columns == 0),
+            PieceType::Bishop => rows == columns && rows > 0,
+            PieceType::Queen => ((rows == 0) != (columns == 0)) || (rows == columns && rows > 0),
         }
-        Ok(())
     }
 
-    async fn bishop_move(&mut self, position_difference: (i8, i8)) -> Result<(), anyhow::Error> {
-        if position_difference.0 == 0
-            || position_difference.1 == 0
-            || position_difference.0.abs() != position_difference.1.abs()
-        {
-            bail!("Incorrect bishop move");
+    pub fn move_to(&mut self, new_position: Position) {
+        let last_row = match self.color {
+            PieceColor::White => 7,
+            PieceColor::Black => 0,
         };
-        Ok(())
-    }
-
-    async fn pawn_move(
-        &mut self,
-        new_position: Position,
-        position_difference: (i8, i8),
-    ) -> anyhow::Result<()> {
-        if self.position.column == new_position.column {
-            if position_difference.0 > 2 {
-                bail!("Can't got further than two tiles");
-            };
-            if position_difference.0 < 1 {
-                bail!("Can't got les than one tile");
-            };
-            if self.times_moved != 0 && position_difference.0 == 2 {
-                bail!("Can't move more than one tile after the first move!");
-            };
-            return Ok(());
-        };
-        if position_difference.1.abs() != 1 {
-            bail!("Movement unreachable by any means");
+        if self.piece_type == PieceType::Pawn && new_position.row == last_row {
+            self.piece_type = PieceType::Queen;
         }
-        Ok(())
+        self.position = new_position;
+        self.times_moved += 1;
     }
 }
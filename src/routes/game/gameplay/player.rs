@@ -1,13 +1,37 @@
+use std::time::Duration;
+
+use tokio::time::Instant;
+
 use crate::routes::game::ws::GameWs;
 
+use super::history::MessageHistory;
+
 #[derive(Debug)]
 pub struct GamePlayer {
     pub id: i32,
     pub ws: GameWs,
+    pub disconnected_at: Option<Instant>,
+    pub history: MessageHistory,
+    /// Round trip time measured at the last heartbeat check
+    pub latency: Option<Duration>,
 }
 
 impl GamePlayer {
     pub fn new(id: i32, ws: GameWs) -> Self {
-        Self { id, ws }
+        Self {
+            id,
+            ws,
+            disconnected_at: None,
+            history: MessageHistory::default(),
+            latency: None,
+        }
+    }
+
+    pub fn is_connected(&self) -> bool {
+        self.disconnected_at.is_none()
+    }
+
+    pub fn latency_ms(&self) -> Option<u64> {
+        self.latency.map(|latency| latency.as_millis() as u64)
     }
 }
@@ -13,6 +13,10 @@ impl Position {
         Position { row, column }
     }
 
+    pub fn is_on_board(&self) -> bool {
+        (0..8).contains(&self.column) && (0..8).contains(&self.row)
+    }
+
     pub fn invert(&self) -> Self {
         Position {
             row: 7 - self.row,
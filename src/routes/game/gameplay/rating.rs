@@ -0,0 +1,154 @@
+//! Glicko-2 rating system, see <http://www.glicko.net/glicko/glicko2.pdf>.
+//! Every game is rated as a rating period of its own.
+
+use std::f64::consts::PI;
+
+/// Converts between the Glicko and the Glicko-2 scale
+const GLICKO2_SCALE: f64 = 173.7178;
+const DEFAULT_RATING: f64 = 1500.0;
+const MAX_DEVIATION: f64 = 350.0;
+const DEFAULT_VOLATILITY: f64 = 0.06;
+/// Constrains how much the volatility can change over time
+const TAU: f64 = 0.5;
+const CONVERGENCE_TOLERANCE: f64 = 0.000001;
+
+#[derive(Clone, Copy, Debug)]
+pub struct Rating {
+    pub rating: f64,
+    pub deviation: f64,
+    pub volatility: f64,
+}
+
+impl Default for Rating {
+    /// Rating of a player who hasn't played yet
+    fn default() -> Self {
+        Self {
+            rating: DEFAULT_RATING,
+            deviation: MAX_DEVIATION,
+            volatility: DEFAULT_VOLATILITY,
+        }
+    }
+}
+
+fn g(phi: f64) -> f64 {
+    1.0 / (1.0 + 3.0 * phi.powi(2) / PI.powi(2)).sqrt()
+}
+
+fn expected_score(mu: f64, opponent_mu: f64, opponent_phi: f64) -> f64 {
+    1.0 / (1.0 + (-g(opponent_phi) * (mu - opponent_mu)).exp())
+}
+
+impl Rating {
+    fn mu(&self) -> f64 {
+        (self.rating - DEFAULT_RATING) / GLICKO2_SCALE
+    }
+
+    fn phi(&self) -> f64 {
+        self.deviation / GLICKO2_SCALE
+    }
+
+    /// The rating after a game with the given score: 1 for a win, 0.5 for a draw and 0 for a loss
+    pub fn updated(&self, opponent: &Rating, score: f64) -> Rating {
+        let (mu, phi, sigma) = (self.mu(), self.phi(), self.volatility);
+        let (opponent_mu, opponent_phi) = (opponent.mu(), opponent.phi());
+        let expected = expected_score(mu, opponent_mu, opponent_phi);
+        let variance = 1.0 / (g(opponent_phi).powi(2) * expected * (1.0 - expected));
+        let improvement = variance * g(opponent_phi) * (score - expected);
+        let sigma = new_volatility(phi, sigma, variance, improvement);
+        let pre_period_phi = (phi.powi(2) + sigma.powi(2)).sqrt();
+        let phi = 1.0 / (1.0 / pre_period_phi.powi(2) + 1.0 / variance).sqrt();
+        let mu = mu + phi.powi(2) * g(opponent_phi) * (score - expected);
+        Rating {
+            rating: mu * GLICKO2_SCALE + DEFAULT_RATING,
+            deviation: (phi * GLICKO2_SCALE).min(MAX_DEVIATION),
+            volatility: sigma,
+        }
+    }
+}
+
+/// Finds the new volatility with the Illinois algorithm, narrowing the brackets A and B of the paper
+fn new_volatility(phi: f64, sigma: f64, variance: f64, improvement: f64) -> f64 {
+    let a = sigma.powi(2).ln();
+    let f = |x: f64| {
+        let ex = x.exp();
+        let denominator = phi.powi(2) + variance + ex;
+        ex * (improvement.powi(2) - denominator) / (2.0 * denominator.powi(2))
+            - (x - a) / TAU.powi(2)
+    };
+    let mut bracket_a = a;
+    let mut bracket_b = if improvement.powi(2) > phi.powi(2) + variance {
+        (improvement.powi(2) - phi.powi(2) - variance).ln()
+    } else {
+        let mut k = 1.0;
+        while f(a - k * TAU) < 0.0 {
+            k += 1.0;
+        }
+        a - k * TAU
+    };
+    let (mut f_bracket_a, mut f_bracket_b) = (f(bracket_a), f(bracket_b));
+    while (bracket_b - bracket_a).abs() > CONVERGENCE_TOLERANCE {
+        let candidate =
+            bracket_a + (bracket_a - bracket_b) * f_bracket_a / (f_bracket_b - f_bracket_a);
+        let f_candidate = f(candidate);
+        if f_candidate * f_bracket_b <= 0.0 {
+            bracket_a = bracket_b;
+            f_bracket_a = f_bracket_b;
+        } else {
+            f_bracket_a /= 2.0;
+        }
+        bracket_b = candidate;
+        f_bracket_b = f_candidate;
+    }
+    (bracket_a / 2.0).exp()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Allowed rounding difference from the reference values
+    const TOLERANCE: f64 = 0.01;
+
+    #[test]
+    fn new_players_after_one_game() {
+        let player = Rating::default();
+        let winner = player.updated(&player, 1.0);
+        let loser = player.updated(&player, 0.0);
+        assert!((winner.rating - 1662.31).abs() < TOLERANCE);
+        assert!((loser.rating - 1337.69).abs() < TOLERANCE);
+        for rating in [winner, loser] {
+            assert!((rating.deviation - 290.32).abs() < TOLERANCE);
+            assert!((rating.volatility - DEFAULT_VOLATILITY).abs() < TOLERANCE);
+        }
+    }
+
+    #[test]
+    fn win_moves_the_ratings_apart() {
+        let player = Rating {
+            rating: 1700.0,
+            deviation: 80.0,
+            volatility: DEFAULT_VOLATILITY,
+        };
+        let opponent = Rating {
+            rating: 1650.0,
+            deviation: 120.0,
+            volatility: DEFAULT_VOLATILITY,
+        };
+        assert!(player.updated(&opponent, 1.0).rating > player.rating);
+        assert!(opponent.updated(&player, 0.0).rating < opponent.rating);
+        // An upset counts the other way round
+        assert!(opponent.updated(&player, 1.0).rating > opponent.rating);
+        assert!(player.updated(&opponent, 0.0).rating < player.rating);
+    }
+
+    #[test]
+    fn draw_between_equals_keeps_the_rating() {
+        let player = Rating {
+            rating: 1800.0,
+            deviation: 60.0,
+            volatility: DEFAULT_VOLATILITY,
+        };
+        let drawn = player.updated(&player, 0.5);
+        assert!((drawn.rating - player.rating).abs() < TOLERANCE);
+    }
+}
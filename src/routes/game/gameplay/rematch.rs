@@ -0,0 +1,116 @@
+use std::time::Duration;
+
+use futures::{future::BoxFuture, FutureExt};
+use sqlx::{Pool, Postgres};
+use tokio::time::{sleep_until, Instant};
+
+use crate::routes::game::{
+    game_registry::GameRegistry,
+    matchmaking::{db::Game, start_game_between},
+    opponent_pair::OpponentPair,
+    piece_color::PieceColor,
+    ws_messages::{GameClientMsg, ServerMsg},
+};
+
+use super::{player::GamePlayer, ws_message::RematchServerMsg, Gameplay};
+
+/// How long the players may agree on a rematch once their game is over
+const REMATCH_WINDOW: Duration = Duration::from_secs(30);
+
+/// Keeps the finished game's connections open for a while,
+/// starting the same game with the colors swapped if both players ask for it.
+/// Boxed as the new game offers a rematch of its own in turn.
+pub fn offer_rematch(
+    db_pool: Pool<Postgres>,
+    game_registry: GameRegistry,
+    game_data: Game,
+    players: OpponentPair,
+) -> BoxFuture<'static, ()> {
+    async move {
+        let OpponentPair {
+            white_player,
+            black_player,
+            ..
+        } = players;
+        if !white_player.is_connected() || !black_player.is_connected() {
+            return;
+        }
+        // Either player may have moved on to another game already
+        let sessions = game_registry.sessions();
+        if sessions.start_waiting(white_player.id).await.is_err() {
+            return;
+        }
+        if sessions.start_waiting(black_player.id).await.is_err() {
+            sessions.stop_waiting(white_player.id).await;
+            return;
+        }
+
+        if agree_on_rematch(&white_player, &black_player).await {
+            start_game_between(
+                &db_pool,
+                &game_registry,
+                game_data.game_type(),
+                game_data.rated,
+                GamePlayer::new(black_player.id, black_player.ws),
+                GamePlayer::new(white_player.id, white_player.ws),
+            )
+            .await;
+        } else {
+            sessions.stop_waiting(white_player.id).await;
+            sessions.stop_waiting(black_player.id).await;
+        }
+    }
+    .boxed()
+}
+
+/// Returns `true` once both players ask for a rematch,
+/// or `false` if either of them declines or leaves, or the window closes
+async fn agree_on_rematch(white_player: &GamePlayer, black_player: &GamePlayer) -> bool {
+    let players = |color| match color {
+        PieceColor::White => white_player,
+        PieceColor::Black => black_player,
+    };
+    let send = |color, msg| async move {
+        let _ = players(color)
+            .ws
+            .send_as_text(&ServerMsg::Rematch(msg))
+            .await;
+    };
+    for color in [PieceColor::White, PieceColor::Black] {
+        send(
+            color,
+            RematchServerMsg::Available {
+                expires_in_secs: REMATCH_WINDOW.as_secs(),
+            },
+        )
+        .await;
+    }
+    let deadline = Instant::now() + REMATCH_WINDOW;
+    let mut offered_by = None;
+    loop {
+        let (color, message) = tokio::select! {
+            message = Gameplay::ws_next(&white_player.ws) => (PieceColor::White, message),
+            message = Gameplay::ws_next(&black_player.ws) => (PieceColor::Black, message),
+            _ = sleep_until(deadline) => {
+                send(PieceColor::White, RematchServerMsg::Expired).await;
+                send(PieceColor::Black, RematchServerMsg::Expired).await;
+                return false;
+            }
+        };
+        match message {
+            Ok(Some(GameClientMsg::Rematch)) if offered_by == Some(color.invert()) => {
+                return true;
+            }
+            Ok(Some(GameClientMsg::Rematch)) => {
+                offered_by = Some(color);
+                send(color.invert(), RematchServerMsg::Offered).await;
+            }
+            Ok(Some(GameClientMsg::RematchDecline)) | Ok(None) => {
+                send(color.invert(), RematchServerMsg::Declined).await;
+                return false;
+            }
+            // Anything else is left over from the game
+            Ok(Some(_)) | Err(_) => {}
+        }
+    }
+}
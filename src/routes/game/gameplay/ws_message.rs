@@ -1,13 +1,77 @@
 use serde::{Deserialize, Serialize};
 
-use crate::routes::game::{piece_color::PieceColor, ws_messages::ChessMove};
+use crate::routes::game::{game_type::GameType, piece_color::PieceColor, ws_messages::ChessMove};
 
-use super::position::Position;
+use super::{
+    chat::ChatLine,
+    error::GameError,
+    outcome::{GameEndReason, GameResult},
+    piece::PieceType,
+    position::Position,
+};
+
+/// Sent to the players of a finished game while they may agree on a rematch
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) enum RematchServerMsg {
+    Available {
+        expires_in_secs: u64,
+    },
+    /// The opponent asks for a rematch
+    Offered,
+    /// The opponent declined or left
+    Declined,
+    Expired,
+}
+
+/// Game message numbered in the order it was sent to the player
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct GameEvent {
+    pub seq: u64,
+    /// Hash of the piece placement at the time of sending, see [`super::history::position_hash`]
+    pub position_hash: u32,
+    pub msg: GameServerMsg,
+}
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub(crate) enum GameServerMsg {
     NewTurn(bool),
-    Error(String),
-    GameEnd(bool),
+    /// Time left on both clocks, sent whenever the running clock changes
+    Clock {
+        white_ms: u64,
+        black_ms: u64,
+    },
+    Error(GameError),
+    GameEnd {
+        result: GameResult,
+        reason: GameEndReason,
+    },
+    /// The game ended too early to count
+    GameAborted,
     PawnMove(ChessMove, Option<(PieceColor, Position)>),
+    DrawOffered(PieceColor),
+    DrawDeclined(PieceColor),
+    DrawOfferLapsed(PieceColor),
+    OpponentDisconnected,
+    OpponentReconnected,
+    State(GameSnapshot),
+    Chat(ChatLine),
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct GameSnapshot {
+    /// `None` for spectators
+    pub color: Option<PieceColor>,
+    pub game_type: GameType,
+    pub rated: bool,
+    pub fen: String,
+    pub side_to_move: PieceColor,
+    /// Moves made so far, as seen from the side of the player or white for spectators
+    pub moves: Vec<ChessMove>,
+    pub captured: Vec<(PieceColor, PieceType)>,
+    /// Color of the player who offered a draw, if the offer is still pending
+    pub draw_offer: Option<PieceColor>,
+    pub white_latency_ms: Option<u64>,
+    pub black_latency_ms: Option<u64>,
+    pub white_clock_ms: u64,
+    pub black_clock_ms: u64,
 }
@@ -0,0 +1,69 @@
+use std::{collections::HashMap, sync::Arc};
+
+use axum::extract::FromRef;
+use rand::{seq::SliceRandom, thread_rng};
+use tokio::sync::{oneshot, Mutex};
+
+use crate::{
+    routes::game::{game_type::GameType, piece_color::PieceColor, ws::GameWs},
+    ServerState,
+};
+
+/// Letters and digits that are hard to mistake for one another
+const CODE_ALPHABET: &[u8] = b"ABCDEFGHJKLMNPQRSTUVWXYZ23456789";
+const CODE_LENGTH: usize = 8;
+
+#[derive(Debug)]
+pub struct Invite {
+    pub host: i32,
+    pub host_color: Option<PieceColor>,
+    pub game_type: GameType,
+    pub rated: bool,
+    pub ws: GameWs,
+    /// Stops the invite from expiring once a guest has joined
+    pub joined: oneshot::Sender<()>,
+}
+
+/// Invites waiting for a guest, each held open by the host's socket
+#[derive(Default, Clone, Debug)]
+pub struct Invites {
+    invites: Arc<Mutex<HashMap<String, Invite>>>,
+}
+
+impl Invites {
+    /// A code no open invite uses
+    pub async fn new_code(&self) -> String {
+        let invites = self.invites.lock().await;
+        loop {
+            let code: String = (0..CODE_LENGTH)
+                .map(|_| *CODE_ALPHABET.choose(&mut thread_rng()).unwrap() as char)
+                .collect();
+            if !invites.contains_key(&code) {
+                return code;
+            }
+        }
+    }
+
+    pub async fn insert(&self, code: String, invite: Invite) {
+        self.invites.lock().await.insert(code, invite);
+    }
+
+    pub async fn remove(&self, code: &str) -> Option<Invite> {
+        self.invites.lock().await.remove(code)
+    }
+
+    /// Takes out the invite for the guest, who can't be its host
+    pub async fn take(&self, code: &str, guest_id: i32) -> Option<Invite> {
+        let mut invites = self.invites.lock().await;
+        if invites.get(code)?.host == guest_id {
+            return None;
+        }
+        invites.remove(code)
+    }
+}
+
+impl FromRef<ServerState> for Invites {
+    fn from_ref(input: &ServerState) -> Self {
+        input.invites.clone()
+    }
+}
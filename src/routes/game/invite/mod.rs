@@ -0,0 +1,174 @@
+use std::time::Duration;
+
+use axum::{
+    extract::{
+        ws::{Message, WebSocket},
+        Path, State, WebSocketUpgrade,
+    },
+    response::Response,
+};
+use invite_state::{Invite, Invites};
+use tokio::{sync::oneshot, time::sleep};
+use ws_message::{InviteRequest, InviteServerMsg};
+
+use crate::{routes::user::jwt::Claims, GlobalState, ServerState};
+
+use super::{
+    game_registry::GameRegistry,
+    gameplay::player::GamePlayer,
+    matchmaking::{send_error, start_hosted_game, wait_until_gone},
+    session_registry::SessionRegistry,
+    ws::GameWs,
+    ws_messages::ServerMsg,
+};
+
+pub mod invite_state;
+pub mod ws_message;
+
+/// How long an invite can be joined
+const INVITE_LIFETIME: Duration = Duration::from_secs(30 * 60);
+
+/// WebSocket of the host, kept open until a guest joins
+#[axum::debug_handler(state = ServerState)]
+pub async fn route_handler(
+    ws: WebSocketUpgrade,
+    State(invites): State<Invites>,
+    State(sessions): State<SessionRegistry>,
+) -> Response {
+    ws.on_upgrade(|socket: WebSocket| handle_ws(GameWs::new(socket), invites, sessions))
+}
+
+async fn handle_ws(ws: GameWs, invites: Invites, sessions: SessionRegistry) {
+    let Ok(Message::Text(request)) = ws.get().await else {
+        return;
+    };
+    let Ok(request) = serde_json::from_str::<InviteRequest>(&request) else {
+        send_error(&ws, InviteServerMsg::Error("Invalid invite".into())).await;
+        return;
+    };
+    if let Err(error) = request.game_type.check() {
+        send_error(&ws, InviteServerMsg::Error(error.to_string())).await;
+        return;
+    }
+    let Ok(claims) = Claims::try_from(request.jwt) else {
+        send_error(&ws, InviteServerMsg::Error("Invalid JWT!".into())).await;
+        return;
+    };
+    if let Err(error) = sessions.start_waiting(claims.sub).await {
+        send_error(&ws, InviteServerMsg::Error(error.to_string())).await;
+        return;
+    }
+
+    let code = invites.new_code().await;
+    let (joined, joined_rx) = oneshot::channel();
+    invites
+        .insert(
+            code.clone(),
+            Invite {
+                host: claims.sub,
+                host_color: request.host_color,
+                game_type: request.game_type,
+                rated: request.rated,
+                ws: ws.clone(),
+                joined,
+            },
+        )
+        .await;
+    tokio::spawn(wait_for_guest(
+        ws.clone(),
+        joined_rx,
+        invites,
+        sessions,
+        claims.sub,
+        code.clone(),
+    ));
+    let _ = ws
+        .send_as_text(&ServerMsg::Invite(InviteServerMsg::Created {
+            code,
+            expires_in_secs: INVITE_LIFETIME.as_secs(),
+        }))
+        .await;
+}
+
+/// Withdraws the invite when the host leaves or it expires
+async fn wait_for_guest(
+    ws: GameWs,
+    joined: oneshot::Receiver<()>,
+    invites: Invites,
+    sessions: SessionRegistry,
+    host_id: i32,
+    code: String,
+) {
+    let expired = tokio::select! {
+        _ = wait_until_gone(&ws) => false,
+        _ = sleep(INVITE_LIFETIME) => true,
+        _ = joined => return,
+    };
+    // A guest may have taken the invite out in the meantime
+    if invites.remove(&code).await.is_none() {
+        return;
+    }
+    sessions.stop_waiting(host_id).await;
+    if expired {
+        let _ = ws
+            .send_as_text(&ServerMsg::Invite(InviteServerMsg::Expired))
+            .await;
+    }
+}
+
+/// WebSocket of the guest, who joins the game right away
+#[axum::debug_handler(state = ServerState)]
+pub async fn join_route_handler(
+    ws: WebSocketUpgrade,
+    Path(code): Path<String>,
+    State(global_state): State<GlobalState>,
+    State(invites): State<Invites>,
+    State(game_registry): State<GameRegistry>,
+) -> Response {
+    ws.on_upgrade(move |socket: WebSocket| {
+        handle_join_ws(
+            global_state,
+            GameWs::new(socket),
+            code,
+            invites,
+            game_registry,
+        )
+    })
+}
+
+async fn handle_join_ws(
+    GlobalState { db_pool }: GlobalState,
+    ws: GameWs,
+    code: String,
+    invites: Invites,
+    game_registry: GameRegistry,
+) {
+    let Ok(Message::Text(jwt)) = ws.get().await else {
+        return;
+    };
+    let Ok(claims) = Claims::try_from(jwt) else {
+        send_error(&ws, InviteServerMsg::Error("Invalid JWT!".into())).await;
+        return;
+    };
+    let sessions = game_registry.sessions();
+    if let Err(error) = sessions.start_waiting(claims.sub).await {
+        send_error(&ws, InviteServerMsg::Error(error.to_string())).await;
+        return;
+    }
+    let Some(invite) = invites.take(&code.to_uppercase(), claims.sub).await else {
+        sessions.stop_waiting(claims.sub).await;
+        send_error(&ws, InviteServerMsg::Error("No such invite".into())).await;
+        return;
+    };
+    let _ = invite.joined.send(());
+    start_hosted_game(
+        &db_pool,
+        &game_registry,
+        GamePlayer::new(invite.host, invite.ws),
+        invite.host_color,
+        GamePlayer::new(claims.sub, ws),
+        invite.game_type,
+        invite.rated,
+    )
+    .await;
+}
@@ -0,0 +1,26 @@
+use serde::{Deserialize, Serialize};
+
+use crate::routes::game::{game_type::GameType, piece_color::PieceColor};
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub(crate) enum InviteServerMsg {
+    /// The invite can be joined with the code until it expires
+    Created {
+        code: String,
+        expires_in_secs: u64,
+    },
+    Expired,
+    Error(String),
+}
+
+/// First message on the invite socket
+#[derive(Deserialize, Debug)]
+pub(crate) struct InviteRequest {
+    pub jwt: String,
+    #[serde(default)]
+    pub host_color: Option<PieceColor>,
+    #[serde(default)]
+    pub game_type: GameType,
+    #[serde(default)]
+    pub rated: bool,
+}
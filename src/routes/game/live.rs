@@ -0,0 +1,87 @@
+use std::time::Duration;
+
+use axum::{
+    extract::{ws::WebSocket, State, WebSocketUpgrade},
+    response::Response,
+    Json,
+};
+use tokio::{sync::mpsc, time::sleep};
+
+use super::{
+    game_registry::{GameRegistry, LiveGame},
+    spectate::{ws_message::SpectatorServerMsg, Spectator, SPECTATOR_BUFFER},
+    ws::GameWs,
+    ws_messages::ServerMsg,
+};
+
+/// How often the featured stream looks for a game while there is none
+const FEATURED_POLL_INTERVAL: Duration = Duration::from_secs(5);
+/// How long the featured stream waits before trying again when it couldn't follow a game
+const FEATURED_RETRY_DELAY: Duration = Duration::from_secs(1);
+
+pub async fn live_games(State(game_registry): State<GameRegistry>) -> Json<Vec<LiveGame>> {
+    let mut live_games = game_registry.live_games().await;
+    live_games.sort_by_key(|live_game| std::cmp::Reverse(live_game.combined_rating()));
+    Json(live_games)
+}
+
+pub async fn featured_route_handler(
+    ws: WebSocketUpgrade,
+    State(game_registry): State<GameRegistry>,
+) -> Response {
+    ws.on_upgrade(move |socket: WebSocket| handle_featured_ws(GameWs::new(socket), game_registry))
+}
+
+/// Follows the highest rated running game, moving on to the next one when it ends
+async fn handle_featured_ws(ws: GameWs, game_registry: GameRegistry) {
+    let mut spectator = Spectator::default();
+    loop {
+        let Some(game_id) = game_registry.featured().await else {
+            if ws
+                .send_as_text(&ServerMsg::Spectator(SpectatorServerMsg::NoLiveGames))
+                .await
+                .is_err()
+            {
+                return;
+            }
+            if !pause(&ws, FEATURED_POLL_INTERVAL).await {
+                return;
+            }
+            continue;
+        };
+        let (tx, rx) = mpsc::channel(SPECTATOR_BUFFER);
+        if !game_registry.spectate(game_id, tx).await {
+            // The game may be stuck in the registry, so it isn't asked for again straight away
+            if !pause(&ws, FEATURED_RETRY_DELAY).await {
+                return;
+            }
+            continue;
+        }
+        if ws
+            .send_as_text(&ServerMsg::Spectator(SpectatorServerMsg::Featured(game_id)))
+            .await
+            .is_err()
+        {
+            return;
+        }
+        if !spectator.follow(&ws, rx, &game_registry, game_id).await {
+            return;
+        }
+    }
+}
+
+/// Waits for the delay, returns `false` if the spectator left in the meantime
+async fn pause(ws: &GameWs, delay: Duration) -> bool {
+    let delay = sleep(delay);
+    tokio::pin!(delay);
+    loop {
+        tokio::select! {
+            _ = &mut delay => return true,
+            message = ws.get() => {
+                if message.is_err() {
+                    return false;
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,122 @@
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+};
+
+use axum::extract::FromRef;
+use serde::{Deserialize, Serialize};
+use tokio::sync::{broadcast, oneshot, Mutex};
+
+use crate::{
+    routes::game::{game_type::GameType, piece_color::PieceColor, ws::GameWs},
+    ServerState,
+};
+
+use super::ws_message::LobbyServerMsg;
+
+/// How many updates may wait for a slow lobby client before it has to start over
+const LOBBY_BUFFER: usize = 64;
+
+/// A seek as shown in the lobby
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct SeekInfo {
+    pub id: u64,
+    pub player: i32,
+    pub username: String,
+    /// Rating in the pool of the game type
+    pub rating: i32,
+    pub game_type: GameType,
+    /// Color of the player who posted the seek, if they picked one
+    pub color: Option<PieceColor>,
+    pub min_rating: Option<i32>,
+    pub max_rating: Option<i32>,
+}
+
+impl SeekInfo {
+    pub fn accepts(&self, rating: i32) -> bool {
+        self.min_rating
+            .map_or(true, |min_rating| rating >= min_rating)
+            && self
+                .max_rating
+                .map_or(true, |max_rating| rating <= max_rating)
+    }
+}
+
+#[derive(Debug)]
+pub struct Seek {
+    pub info: SeekInfo,
+    pub ws: GameWs,
+    /// Stops watching the player's socket once someone accepts the seek
+    pub accepted: oneshot::Sender<()>,
+}
+
+/// Open seeks, each held open by its player's socket, and the lobby following them
+#[derive(Clone, Debug)]
+pub struct Seeks {
+    seeks: Arc<Mutex<HashMap<u64, Seek>>>,
+    next_id: Arc<AtomicU64>,
+    updates: broadcast::Sender<LobbyServerMsg>,
+}
+
+impl Default for Seeks {
+    fn default() -> Self {
+        Self {
+            seeks: Default::default(),
+            next_id: Default::default(),
+            updates: broadcast::channel(LOBBY_BUFFER).0,
+        }
+    }
+}
+
+impl Seeks {
+    pub fn next_id(&self) -> u64 {
+        self.next_id.fetch_add(1, Ordering::Relaxed)
+    }
+
+    pub async fn insert(&self, seek: Seek) {
+        let mut seeks = self.seeks.lock().await;
+        let _ = self
+            .updates
+            .send(LobbyServerMsg::SeekAdded(seek.info.clone()));
+        seeks.insert(seek.info.id, seek);
+    }
+
+    pub async fn remove(&self, id: u64) -> Option<Seek> {
+        let mut seeks = self.seeks.lock().await;
+        let seek = seeks.remove(&id)?;
+        let _ = self.updates.send(LobbyServerMsg::SeekRemoved(id));
+        Some(seek)
+    }
+
+    pub async fn info(&self, id: u64) -> Option<SeekInfo> {
+        let seeks = self.seeks.lock().await;
+        seeks.get(&id).map(|seek| seek.info.clone())
+    }
+
+    /// Takes out the seek for the player accepting it, who can't be the one who posted it
+    pub async fn take(&self, id: u64, player_id: i32) -> Option<Seek> {
+        let mut seeks = self.seeks.lock().await;
+        if seeks.get(&id)?.info.player == player_id {
+            return None;
+        }
+        let seek = seeks.remove(&id)?;
+        let _ = self.updates.send(LobbyServerMsg::SeekRemoved(id));
+        Some(seek)
+    }
+
+    /// Every open seek, along with the updates that follow them
+    pub async fn subscribe(&self) -> (Vec<SeekInfo>, broadcast::Receiver<LobbyServerMsg>) {
+        let seeks = self.seeks.lock().await;
+        let open_seeks = seeks.values().map(|seek| seek.info.clone()).collect();
+        (open_seeks, self.updates.subscribe())
+    }
+}
+
+impl FromRef<ServerState> for Seeks {
+    fn from_ref(input: &ServerState) -> Self {
+        input.seeks.clone()
+    }
+}
@@ -0,0 +1,233 @@
+use anyhow::{anyhow, ensure};
+use axum::{
+    extract::{
+        ws::{Message, WebSocket},
+        Path, State, WebSocketUpgrade,
+    },
+    response::Response,
+};
+use lobby_state::{Seek, SeekInfo, Seeks};
+use sqlx::{Pool, Postgres};
+use tokio::sync::{broadcast::error::RecvError, oneshot};
+use ws_message::{LobbyServerMsg, SeekRequest};
+
+use crate::{routes::user::jwt::Claims, GlobalState, ServerState};
+
+use super::{
+    game_registry::GameRegistry,
+    gameplay::player::GamePlayer,
+    matchmaking::{db::get_player_summary, send_error, start_hosted_game, wait_until_gone},
+    session_registry::SessionRegistry,
+    ws::GameWs,
+    ws_messages::ServerMsg,
+};
+
+pub mod lobby_state;
+pub mod ws_message;
+
+/// WebSocket following the open seeks
+pub async fn route_handler(ws: WebSocketUpgrade, State(seeks): State<Seeks>) -> Response {
+    ws.on_upgrade(|socket: WebSocket| handle_ws(GameWs::new(socket), seeks))
+}
+
+async fn handle_ws(ws: GameWs, seeks: Seeks) {
+    let gone = wait_until_gone(&ws);
+    tokio::pin!(gone);
+    let (open_seeks, mut updates) = seeks.subscribe().await;
+    let mut message = LobbyServerMsg::Seeks(open_seeks);
+    loop {
+        if ws.send_as_text(&ServerMsg::Lobby(message)).await.is_err() {
+            return;
+        }
+        message = tokio::select! {
+            _ = &mut gone => return,
+            update = updates.recv() => match update {
+                Ok(update) => update,
+                // Too far behind to catch up, start over from the current seeks
+                Err(RecvError::Lagged(_)) => {
+                    let (open_seeks, resubscribed) = seeks.subscribe().await;
+                    updates = resubscribed;
+                    LobbyServerMsg::Seeks(open_seeks)
+                }
+                Err(RecvError::Closed) => return,
+            },
+        };
+    }
+}
+
+/// WebSocket of a player posting a seek, kept open until someone accepts it
+#[axum::debug_handler(state = ServerState)]
+pub async fn seek_route_handler(
+    ws: WebSocketUpgrade,
+    State(global_state): State<GlobalState>,
+    State(seeks): State<Seeks>,
+    State(sessions): State<SessionRegistry>,
+) -> Response {
+    ws.on_upgrade(|socket: WebSocket| {
+        handle_seek_ws(global_state, GameWs::new(socket), seeks, sessions)
+    })
+}
+
+async fn handle_seek_ws(
+    GlobalState { db_pool }: GlobalState,
+    ws: GameWs,
+    seeks: Seeks,
+    sessions: SessionRegistry,
+) {
+    let Ok(Message::Text(request)) = ws.get().await else {
+        return;
+    };
+    let Ok(request) = serde_json::from_str::<SeekRequest>(&request) else {
+        send_error(&ws, LobbyServerMsg::Error("Invalid seek".into())).await;
+        return;
+    };
+    let info = match seek_info(&db_pool, seeks.next_id(), request).await {
+        Ok(info) => info,
+        Err(error) => {
+            send_error(&ws, LobbyServerMsg::Error(error.to_string())).await;
+            return;
+        }
+    };
+    if let Err(error) = sessions.start_waiting(info.player).await {
+        send_error(&ws, LobbyServerMsg::Error(error.to_string())).await;
+        return;
+    }
+
+    let (id, player_id) = (info.id, info.player);
+    let (accepted, accepted_rx) = oneshot::channel();
+    seeks
+        .insert(Seek {
+            info,
+            ws: ws.clone(),
+            accepted,
+        })
+        .await;
+    tokio::spawn(wait_for_opponent(
+        ws.clone(),
+        accepted_rx,
+        seeks,
+        sessions,
+        player_id,
+        id,
+    ));
+    let _ = ws
+        .send_as_text(&ServerMsg::Lobby(LobbyServerMsg::SeekPosted(id)))
+        .await;
+}
+
+async fn seek_info(
+    db_pool: &Pool<Postgres>,
+    id: u64,
+    request: SeekRequest,
+) -> anyhow::Result<SeekInfo> {
+    request.game_type.check()?;
+    if let (Some(min_rating), Some(max_rating)) = (request.min_rating, request.max_rating) {
+        ensure!(min_rating <= max_rating, "The rating range is empty");
+    }
+    let claims = Claims::try_from(request.jwt).map_err(|_| anyhow!("Invalid JWT!"))?;
+    let rating_pool = request.game_type.rating_pool();
+    let player_summary = get_player_summary(db_pool, claims.sub, &rating_pool).await?;
+    Ok(SeekInfo {
+        id,
+        player: player_summary.id,
+        username: player_summary.username,
+        rating: player_summary.rating,
+        game_type: request.game_type,
+        color: request.color,
+        min_rating: request.min_rating,
+        max_rating: request.max_rating,
+    })
+}
+
+/// Withdraws the seek when its player leaves
+async fn wait_for_opponent(
+    ws: GameWs,
+    accepted: oneshot::Receiver<()>,
+    seeks: Seeks,
+    sessions: SessionRegistry,
+    player_id: i32,
+    id: u64,
+) {
+    tokio::select! {
+        _ = wait_until_gone(&ws) => {}
+        _ = accepted => return,
+    }
+    // Someone may have accepted the seek in the meantime
+    if seeks.remove(id).await.is_some() {
+        sessions.stop_waiting(player_id).await;
+    }
+}
+
+/// WebSocket of the player accepting a seek, who joins the game right away
+#[axum::debug_handler(state = ServerState)]
+pub async fn accept_route_handler(
+    ws: WebSocketUpgrade,
+    Path(seek_id): Path<u64>,
+    State(global_state): State<GlobalState>,
+    State(seeks): State<Seeks>,
+    State(game_registry): State<GameRegistry>,
+) -> Response {
+    ws.on_upgrade(move |socket: WebSocket| {
+        handle_accept_ws(
+            global_state,
+            GameWs::new(socket),
+            seek_id,
+            seeks,
+            game_registry,
+        )
+    })
+}
+
+async fn handle_accept_ws(
+    GlobalState { db_pool }: GlobalState,
+    ws: GameWs,
+    seek_id: u64,
+    seeks: Seeks,
+    game_registry: GameRegistry,
+) {
+    let Ok(Message::Text(jwt)) = ws.get().await else {
+        return;
+    };
+    let Ok(claims) = Claims::try_from(jwt) else {
+        send_error(&ws, LobbyServerMsg::Error("Invalid JWT!".into())).await;
+        return;
+    };
+    let Some(info) = seeks.info(seek_id).await else {
+        send_error(&ws, LobbyServerMsg::Error("No such seek".into())).await;
+        return;
+    };
+    let rating_pool = info.game_type.rating_pool();
+    let Ok(player_summary) = get_player_summary(&db_pool, claims.sub, &rating_pool).await else {
+        send_error(&ws, LobbyServerMsg::Error("Unknown player".into())).await;
+        return;
+    };
+    if !info.accepts(player_summary.rating) {
+        send_error(
+            &ws,
+            LobbyServerMsg::Error("Your rating is outside the seek's range".into()),
+        )
+        .await;
+        return;
+    }
+    let sessions = game_registry.sessions();
+    if let Err(error) = sessions.start_waiting(claims.sub).await {
+        send_error(&ws, LobbyServerMsg::Error(error.to_string())).await;
+        return;
+    }
+    let Some(seek) = seeks.take(seek_id, claims.sub).await else {
+        sessions.stop_waiting(claims.sub).await;
+        send_error(&ws, LobbyServerMsg::Error("No such seek".into())).await;
+        return;
+    };
+    let _ = seek.accepted.send(());
+    start_hosted_game(
+        &db_pool,
+        &game_registry,
+        GamePlayer::new(seek.info.player, seek.ws),
+        seek.info.color,
+        GamePlayer::new(claims.sub, ws),
+        seek.info.game_type,
+        true,
+    )
+    .await;
+}
@@ -0,0 +1,30 @@
+use serde::{Deserialize, Serialize};
+
+use crate::routes::game::{game_type::GameType, piece_color::PieceColor};
+
+use super::lobby_state::SeekInfo;
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub(crate) enum LobbyServerMsg {
+    /// Every open seek, sent on joining the lobby
+    Seeks(Vec<SeekInfo>),
+    SeekAdded(SeekInfo),
+    SeekRemoved(u64),
+    /// The player's own seek is open under this id
+    SeekPosted(u64),
+    Error(String),
+}
+
+/// First message on the seek socket
+#[derive(Deserialize, Debug)]
+pub(crate) struct SeekRequest {
+    pub jwt: String,
+    #[serde(default)]
+    pub game_type: GameType,
+    #[serde(default)]
+    pub color: Option<PieceColor>,
+    #[serde(default)]
+    pub min_rating: Option<i32>,
+    #[serde(default)]
+    pub max_rating: Option<i32>,
+}
@@ -0,0 +1,39 @@
+use std::cmp::Ordering;
+
+use crate::routes::game::piece_color::PieceColor;
+
+/// Latest games looked at when balancing colors
+pub const COLOR_HISTORY_LENGTH: i64 = 10;
+
+/// How much the player leaned towards white recently: first by the number of games
+/// with white over black, then by how many of their latest games in a row were white
+fn white_surplus(colors: &[PieceColor]) -> (i32, i32) {
+    let balance = colors
+        .iter()
+        .map(|color| match color {
+            PieceColor::White => 1,
+            PieceColor::Black => -1,
+        })
+        .sum();
+    let Some(last_color) = colors.first() else {
+        return (balance, 0);
+    };
+    let streak = colors
+        .iter()
+        .take_while(|color| *color == last_color)
+        .count() as i32;
+    match last_color {
+        PieceColor::White => (balance, streak),
+        PieceColor::Black => (balance, -streak),
+    }
+}
+
+/// Whether the first player should get white, given both players' latest colors,
+/// the most recent first. Ties are broken at random.
+pub fn first_plays_white(first: &[PieceColor], second: &[PieceColor]) -> bool {
+    match white_surplus(first).cmp(&white_surplus(second)) {
+        Ordering::Less => true,
+        Ordering::Greater => false,
+        Ordering::Equal => rand::random(),
+    }
+}
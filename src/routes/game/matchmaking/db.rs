@@ -1,7 +1,15 @@
 use anyhow::anyhow;
 use chrono::prelude::*;
 use serde::{Deserialize, Serialize};
-use sqlx::{postgres::PgQueryResult, Pool, Postgres};
+use sqlx::{Pool, Postgres};
+
+use crate::routes::game::{
+    game_type::{GameType, TimeControl, Variant},
+    gameplay::{outcome::GameStatus, rating::Rating},
+    piece_color::PieceColor,
+};
+
+use super::color_history::COLOR_HISTORY_LENGTH;
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct Game {
@@ -11,28 +19,102 @@ pub struct Game {
     pub player_black: i32,
     pub player_white: i32,
     pub winner: Option<i32>,
+    pub termination: Option<String>,
+    pub status: String,
+    pub status_reason: Option<String>,
+    pub result: Option<String>,
+    pub variant: String,
+    /// Seconds, `None` for games from before clocks were kept
+    pub clock_initial: Option<i32>,
+    pub clock_increment: Option<i32>,
+    /// Whether the result counts towards the players' ratings
+    pub rated: bool,
+}
+
+impl Game {
+    pub fn game_type(&self) -> GameType {
+        let default = GameType::default();
+        let time_control = match (self.clock_initial, self.clock_increment) {
+            (Some(initial_secs), Some(increment_secs)) => TimeControl {
+                initial_secs: initial_secs as u32,
+                increment_secs: increment_secs as u32,
+            },
+            _ => default.time_control,
+        };
+        GameType {
+            time_control,
+            variant: Variant::from_name(&self.variant).unwrap_or(default.variant),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct PlayerSummary {
+    pub id: i32,
+    pub username: String,
+    /// Rounded Glicko-2 rating in the pool the summary was made for
+    pub rating: i32,
+}
+
+pub async fn get_player_summary(
+    db_pool: &Pool<Postgres>,
+    player_id: i32,
+    rating_pool: &str,
+) -> anyhow::Result<PlayerSummary> {
+    sqlx::query_as!(
+        PlayerSummary,
+        r#"SELECT p.id, p.username, round(COALESCE(r.rating, $3))::int AS "rating!" FROM player p LEFT JOIN player_rating r ON r.player = p.id AND r.pool = $2 WHERE p.id = $1"#,
+        player_id,
+        rating_pool,
+        Rating::default().rating
+    )
+    .fetch_one(db_pool)
+    .await
+    .map_err(|err| anyhow!(err))
 }
 
 pub async fn create_game(
     db_pool: &Pool<Postgres>,
     username_black: i32,
     username_white: i32,
+    game_type: GameType,
+    rated: bool,
 ) -> anyhow::Result<Game> {
     sqlx::query_as!(
         Game,
-        "INSERT INTO game (started_at, player_black, player_white) VALUES ($1, $2, $3) RETURNING *",
+        "INSERT INTO game (started_at, player_black, player_white, variant, clock_initial, clock_increment, rated) VALUES ($1, $2, $3, $4, $5, $6, $7) RETURNING *",
         Utc::now().naive_utc(),
         username_black,
-        username_white
+        username_white,
+        game_type.variant.get_name(),
+        game_type.time_control.initial_secs as i32,
+        game_type.time_control.increment_secs as i32,
+        rated
     )
     .fetch_one(db_pool)
     .await
     .map_err(|err| anyhow!(err))
 }
 
-pub async fn remove_game(db_pool: &Pool<Postgres>, game: Game) -> anyhow::Result<PgQueryResult> {
-    sqlx::query!("DELETE FROM game WHERE id = $1", game.id)
-        .execute(db_pool)
-        .await
-        .map_err(|err| anyhow!(err))
+/// Colors the player had in their latest games, the most recent first.
+/// Aborted games are left out as they were never really played.
+pub async fn recent_colors(
+    db_pool: &Pool<Postgres>,
+    player_id: i32,
+) -> anyhow::Result<Vec<PieceColor>> {
+    let games = sqlx::query_scalar!(
+        r#"SELECT player_white = $1 AS "is_white!" FROM game WHERE (player_white = $1 OR player_black = $1) AND status <> $2 ORDER BY started_at DESC LIMIT $3"#,
+        player_id,
+        GameStatus::Aborted.get_name(),
+        COLOR_HISTORY_LENGTH
+    )
+    .fetch_all(db_pool)
+    .await?;
+    Ok(games
+        .into_iter()
+        .map(|is_white| match is_white {
+            true => PieceColor::White,
+            false => PieceColor::Black,
+        })
+        .collect())
 }
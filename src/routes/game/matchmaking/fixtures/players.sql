@@ -0,0 +1,3 @@
+INSERT INTO player (id, username, password_hash, salt) OVERRIDING SYSTEM VALUE
+VALUES (1, 'white_knight', '', ''),
+  (2, 'black_bishop', '', '');
@@ -1,34 +1,162 @@
-use std::{collections::VecDeque, sync::Arc};
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::Arc,
+    time::Duration,
+};
 
 use axum::extract::FromRef;
-use tokio::{sync::Mutex, task::JoinHandle};
+use tokio::{sync::Mutex, task::JoinHandle, time::Instant};
 
-use crate::{routes::game::ws::GameWs, ServerState};
+use crate::{
+    routes::game::{game_type::GameType, ws::GameWs},
+    ServerState,
+};
+
+use super::ws_message::MatchmakingServerMsg;
+
+/// Rating difference a player accepts right after joining the queue
+const INITIAL_RATING_WINDOW: i32 = 100;
+/// How much the window widens with every [`RATING_WINDOW_GROWTH_INTERVAL`] of waiting
+const RATING_WINDOW_GROWTH: i32 = 50;
+const RATING_WINDOW_GROWTH_INTERVAL: Duration = Duration::from_secs(5);
+const MAX_RATING_WINDOW: i32 = 1000;
+/// How many of the latest matched players' waits the estimated wait is based on
+const RECENT_WAITS: usize = 20;
 
 #[derive(Debug)]
 pub struct MatchmakingPlayer {
     pub id: i32,
     pub ws: GameWs,
     pub echo: JoinHandle<()>,
+    pub game_type: GameType,
+    /// Rating in the pool of the game type
+    pub rating: i32,
+    pub joined_at: Instant,
 }
 
 impl MatchmakingPlayer {
-    pub fn new(id: i32, ws: GameWs, echo: JoinHandle<()>) -> Self {
-        MatchmakingPlayer { id, ws, echo }
+    pub fn new(
+        id: i32,
+        ws: GameWs,
+        echo: JoinHandle<()>,
+        game_type: GameType,
+        rating: i32,
+    ) -> Self {
+        MatchmakingPlayer {
+            id,
+            ws,
+            echo,
+            game_type,
+            rating,
+            joined_at: Instant::now(),
+        }
+    }
+
+    /// Largest rating difference the player accepts, widening the longer they wait
+    pub fn rating_window(&self) -> i32 {
+        let waited = self.joined_at.elapsed().as_secs() / RATING_WINDOW_GROWTH_INTERVAL.as_secs();
+        let window = INITIAL_RATING_WINDOW + waited as i32 * RATING_WINDOW_GROWTH;
+        window.min(MAX_RATING_WINDOW)
+    }
+
+    fn accepts(&self, other: &MatchmakingPlayer) -> bool {
+        (self.rating - other.rating).abs() <= self.rating_window()
     }
 }
 
+/// Players waiting for a game, in a separate queue for every game type
 #[derive(Default, Clone, Debug)]
 pub struct UserQueue {
-    pub state: Arc<Mutex<VecDeque<MatchmakingPlayer>>>,
+    queues: Arc<Mutex<HashMap<GameType, VecDeque<MatchmakingPlayer>>>>,
+    /// How long the latest matched players waited, by game type
+    recent_waits: Arc<Mutex<HashMap<GameType, VecDeque<Duration>>>>,
 }
 
 impl UserQueue {
     pub async fn push(&self, matchmaking_player: MatchmakingPlayer) {
-        self.state.lock().await.push_back(matchmaking_player);
+        let mut queues = self.queues.lock().await;
+        let queue = queues.entry(matchmaking_player.game_type).or_default();
+        queue.push_back(matchmaking_player);
     }
-    pub async fn pop(&self) -> Option<MatchmakingPlayer> {
-        self.state.lock().await.pop_front()
+
+    pub async fn remove(&self, user_id: i32) -> Option<MatchmakingPlayer> {
+        let mut queues = self.queues.lock().await;
+        queues.values_mut().find_map(|queue| {
+            let index = queue.iter().position(|player| player.id == user_id)?;
+            queue.remove(index)
+        })
+    }
+
+    /// Takes out every pair of players within each other's rating window.
+    /// Those who have waited the longest get the closest opponent first.
+    pub async fn take_pairs(&self) -> Vec<(MatchmakingPlayer, MatchmakingPlayer)> {
+        let mut queues = self.queues.lock().await;
+        let mut pairs = Vec::new();
+        for queue in queues.values_mut() {
+            Self::take_pairs_from(queue, &mut pairs);
+        }
+        queues.retain(|_, queue| !queue.is_empty());
+        let mut recent_waits = self.recent_waits.lock().await;
+        for (player, opponent) in &pairs {
+            let waits = recent_waits.entry(player.game_type).or_default();
+            waits.push_back(player.joined_at.elapsed());
+            waits.push_back(opponent.joined_at.elapsed());
+            while waits.len() > RECENT_WAITS {
+                waits.pop_front();
+            }
+        }
+        pairs
+    }
+
+    /// Where every player in the queue stands, along with their connection
+    pub async fn statuses(&self) -> Vec<(GameWs, MatchmakingServerMsg)> {
+        let queues = self.queues.lock().await;
+        let recent_waits = self.recent_waits.lock().await;
+        let mut statuses = Vec::new();
+        for (game_type, queue) in queues.iter() {
+            let average_wait = recent_waits
+                .get(game_type)
+                .map(|waits| waits.iter().sum::<Duration>() / waits.len() as u32);
+            for (index, player) in queue.iter().enumerate() {
+                let status = MatchmakingServerMsg::QueueStatus {
+                    position: index + 1,
+                    queue_size: queue.len(),
+                    rating_window: player.rating_window(),
+                    estimated_wait_secs: average_wait.map(|average_wait| {
+                        average_wait
+                            .saturating_sub(player.joined_at.elapsed())
+                            .as_secs()
+                    }),
+                };
+                statuses.push((player.ws.clone(), status));
+            }
+        }
+        statuses
+    }
+
+    fn take_pairs_from(
+        queue: &mut VecDeque<MatchmakingPlayer>,
+        pairs: &mut Vec<(MatchmakingPlayer, MatchmakingPlayer)>,
+    ) {
+        let mut index = 0;
+        while index < queue.len() {
+            let player = &queue[index];
+            let opponent_index = queue
+                .iter()
+                .enumerate()
+                .skip(index + 1)
+                .filter(|(_, other)| player.accepts(other) && other.accepts(player))
+                .min_by_key(|(_, other)| (player.rating - other.rating).abs())
+                .map(|(opponent_index, _)| opponent_index);
+            let Some(opponent_index) = opponent_index else {
+                index += 1;
+                continue;
+            };
+            // The opponent comes later in the queue, so removing it first keeps `index` valid
+            let opponent = queue.remove(opponent_index).unwrap();
+            let player = queue.remove(index).unwrap();
+            pairs.push((player, opponent));
+        }
     }
 }
 
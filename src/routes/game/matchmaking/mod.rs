@@ -6,43 +6,81 @@ use axum::{
     },
     response::Response,
 };
-use db::{create_game, remove_game, Game};
+use std::time::Duration;
+
+use color_history::first_plays_white;
+use db::{create_game, get_player_summary, recent_colors};
 use matchmaking_state::{MatchmakingPlayer, UserQueue};
 use sqlx::{Pool, Postgres};
-use ws_message::MatchmakingServerMsg;
+use tokio::time::{interval, Interval};
+use ws_message::{JoinRequest, MatchmakingClientMsg, MatchmakingServerMsg};
 
 use crate::{routes::user::jwt::Claims, GlobalState, ServerState};
 
 use super::{
-    gameplay::Gameplay, opponent_pair::OpponentPair, piece_color::PieceColor, ws::GameWs,
+    game_registry::GameRegistry,
+    game_type::GameType,
+    gameplay::{player::GamePlayer, Gameplay},
+    opponent_pair::OpponentPair,
+    piece_color::PieceColor,
+    session_registry::SessionRegistry,
+    ws::{GameWs, HEARTBEAT_INTERVAL},
     ws_messages::ServerMsg,
 };
 
+pub mod color_history;
 pub mod db;
 pub mod matchmaking_state;
 pub mod ws_message;
+
+/// How often the queue is searched for players to pair up
+const MATCHING_INTERVAL: Duration = Duration::from_secs(1);
+/// How often the players in the queue hear where they stand
+const QUEUE_STATUS_INTERVAL: Duration = Duration::from_secs(5);
+
 #[debug_handler(state=ServerState)]
 pub async fn route_handler(
     ws: WebSocketUpgrade,
     State(queue_state): State<UserQueue>,
+    State(game_registry): State<GameRegistry>,
     State(global_state): State<GlobalState>,
 ) -> Response {
-    ws.on_upgrade(|socket: WebSocket| handle_ws(global_state, socket, queue_state))
+    ws.on_upgrade(|socket: WebSocket| {
+        handle_ws(
+            global_state,
+            GameWs::new(socket),
+            queue_state,
+            game_registry,
+        )
+    })
 }
 
 pub async fn handle_ws(
     GlobalState { db_pool }: GlobalState,
-    socket: WebSocket,
+    ws: GameWs,
     user_queue: UserQueue,
+    game_registry: GameRegistry,
 ) {
-    let ws = GameWs::new(socket);
-
     // Await authentication
-    let Ok(Message::Text(jwt_str)) = ws.get().await else {
+    let Ok(Message::Text(request)) = ws.get().await else {
         return;
     };
+    // A bare JWT asks for the default game type
+    let JoinRequest { jwt, game_type } =
+        serde_json::from_str(&request).unwrap_or_else(|_| JoinRequest {
+            jwt: request,
+            game_type: GameType::default(),
+        });
+    if let Err(error) = game_type.check() {
+        let _ = ws
+            .send_as_text(&ServerMsg::Matchmaking(MatchmakingServerMsg::Error(
+                error.to_string(),
+            )))
+            .await;
+        return;
+    }
     // Check if the claims are correct
-    let claims = Claims::try_from(jwt_str);
+    let claims = Claims::try_from(jwt);
     let claims = match claims {
         Ok(claims) => claims,
         Err(_) => {
@@ -55,96 +93,389 @@ pub async fn handle_ws(
         }
     };
 
-    let user_in_queue = user_queue
-        .state
-        .lock()
-        .await
-        .make_contiguous()
-        .iter()
-        .any(|user| user.id == claims.sub);
-    if user_in_queue {
-        ws.send_as_text(&MatchmakingServerMsg::Error(
-            "User already in the queue".into(),
-        ))
-        .await
-        .unwrap();
+    // Resume the game the player is still part of, if there is one
+    let ws = match game_registry.reconnect(claims.sub, ws).await {
+        Ok(()) => return,
+        Err(ws) => ws,
+    };
+
+    let sessions = game_registry.sessions();
+    if let Err(error) = sessions.start_waiting(claims.sub).await {
+        let _ = ws
+            .send_as_text(&ServerMsg::Matchmaking(MatchmakingServerMsg::Error(
+                error.to_string(),
+            )))
+            .await;
         return;
     }
 
-    // Create a service for matchmaking player
-    let echo_task = tokio::spawn(ws_matchmaking(ws.clone(), user_queue.clone(), claims.sub));
-    let matchmaking_player = MatchmakingPlayer::new(claims.sub, ws, echo_task);
-    // check if we have 2 players. if so, start game.
-    let matchmaking_opponent = match user_queue.pop().await {
-        // Push user into queue and return. We do not need to continue the function.
-        None => {
-            matchmaking_player
-                .ws
-                .send_as_text(&MatchmakingServerMsg::Searching)
-                .await
-                .unwrap();
-            user_queue.push(matchmaking_player).await;
-            return;
-        }
-        // Opponent for current player found!
-        Some(player) => player,
-    };
-    // Insert info about the new game into the database
-    let Ok(game_data) = create_game(&db_pool, matchmaking_player.id, matchmaking_opponent.id).await
-    else {
+    let rating_pool = game_type.rating_pool();
+    let Ok(player_summary) = get_player_summary(&db_pool, claims.sub, &rating_pool).await else {
+        sessions.stop_waiting(claims.sub).await;
+        let _ = ws
+            .send_as_text(&ServerMsg::Matchmaking(MatchmakingServerMsg::Error(
+                "Unknown player".into(),
+            )))
+            .await;
         return;
     };
-    // Inform the players of the new game
-    let _ = matchmaking_player
-        .ws
-        .send_as_text(&ServerMsg::Matchmaking(MatchmakingServerMsg::Success {
-            color: PieceColor::Black,
-        }))
-        .await;
-    let _ = matchmaking_opponent
+
+    // Create a service for matchmaking player
+    let echo_task = tokio::spawn(ws_matchmaking(
+        ws.clone(),
+        user_queue.clone(),
+        sessions.clone(),
+        claims.sub,
+    ));
+    let matchmaking_player =
+        MatchmakingPlayer::new(claims.sub, ws, echo_task, game_type, player_summary.rating);
+    matchmaking_player
         .ws
-        .send_as_text(&ServerMsg::Matchmaking(MatchmakingServerMsg::Success {
-            color: PieceColor::White,
-        }))
+        .send_as_text(&MatchmakingServerMsg::Searching)
+        .await
+        .unwrap();
+    user_queue.push(matchmaking_player).await;
+    // Someone in the queue may be waiting for just this player
+    match_players(&db_pool, &user_queue, &game_registry).await;
+}
+
+/// Keeps pairing up the players in the queue as their rating windows widen
+pub async fn run_matching(
+    GlobalState { db_pool }: GlobalState,
+    user_queue: UserQueue,
+    game_registry: GameRegistry,
+) {
+    let mut matching_interval = interval(MATCHING_INTERVAL);
+    let mut status_interval = interval(QUEUE_STATUS_INTERVAL);
+    loop {
+        tokio::select! {
+            _ = matching_interval.tick() => {
+                match_players(&db_pool, &user_queue, &game_registry).await;
+            }
+            _ = status_interval.tick() => {
+                send_queue_statuses(&user_queue).await;
+            }
+        }
+    }
+}
+
+async fn send_queue_statuses(user_queue: &UserQueue) {
+    for (ws, status) in user_queue.statuses().await {
+        let _ = ws.send_as_text(&ServerMsg::Matchmaking(status)).await;
+    }
+}
+
+async fn match_players(
+    db_pool: &Pool<Postgres>,
+    user_queue: &UserQueue,
+    game_registry: &GameRegistry,
+) {
+    for (matchmaking_opponent, matchmaking_player) in user_queue.take_pairs().await {
+        start_game(
+            db_pool,
+            game_registry,
+            matchmaking_opponent,
+            matchmaking_player,
+        )
         .await;
+    }
+}
 
+/// Starts a game, giving white to the player who had it less often recently
+async fn start_game(
+    db_pool: &Pool<Postgres>,
+    game_registry: &GameRegistry,
+    matchmaking_opponent: MatchmakingPlayer,
+    matchmaking_player: MatchmakingPlayer,
+) {
+    // Without a history to go by, the colors are drawn at random
+    let opponent_colors = recent_colors(db_pool, matchmaking_opponent.id)
+        .await
+        .unwrap_or_default();
+    let player_colors = recent_colors(db_pool, matchmaking_player.id)
+        .await
+        .unwrap_or_default();
+    let (white, black) = match first_plays_white(&opponent_colors, &player_colors) {
+        true => (matchmaking_opponent, matchmaking_player),
+        false => (matchmaking_player, matchmaking_opponent),
+    };
     // Stop the echo services
-    matchmaking_player.echo.abort();
-    matchmaking_opponent.echo.abort();
-    let opponent_pair = OpponentPair::new(matchmaking_opponent, matchmaking_player);
-    tokio::spawn(game_session(db_pool.clone(), game_data, opponent_pair));
+    white.echo.abort();
+    black.echo.abort();
+    start_game_between(
+        db_pool,
+        game_registry,
+        white.game_type,
+        true,
+        GamePlayer::new(white.id, white.ws),
+        GamePlayer::new(black.id, black.ws),
+    )
+    .await;
+}
+
+/// Starts a game between two players whose colors are already settled
+pub(crate) async fn start_game_between(
+    db_pool: &Pool<Postgres>,
+    game_registry: &GameRegistry,
+    game_type: GameType,
+    rated: bool,
+    white: GamePlayer,
+    black: GamePlayer,
+) {
+    let player_ids = [white.id, black.id];
+    // Everything that can fail is done before the game is announced
+    let prepared = async {
+        let rating_pool = game_type.rating_pool();
+        let white_summary = get_player_summary(db_pool, white.id, &rating_pool).await?;
+        let black_summary = get_player_summary(db_pool, black.id, &rating_pool).await?;
+        // Insert info about the new game into the database
+        let game_data = create_game(db_pool, black.id, white.id, game_type, rated).await?;
+        anyhow::Ok((game_data, white_summary, black_summary))
+    };
+    let (game_data, white_summary, black_summary) = match prepared.await {
+        Ok(prepared) => prepared,
+        Err(error) => {
+            let error =
+                ServerMsg::Matchmaking(MatchmakingServerMsg::GameDropped(error.to_string()));
+            for player in [&white, &black] {
+                let _ = player.ws.send_as_text(&error).await;
+            }
+            stop_waiting(game_registry, player_ids).await;
+            return;
+        }
+    };
+    // Inform the players of the new game
+    for (player, color) in [(&white, PieceColor::White), (&black, PieceColor::Black)] {
+        let _ = player
+            .ws
+            .send_as_text(&ServerMsg::Matchmaking(MatchmakingServerMsg::Success {
+                color,
+            }))
+            .await;
+    }
+
+    let opponent_pair = OpponentPair::new(white, black);
+    Gameplay::spawn(
+        db_pool.clone(),
+        game_registry.clone(),
+        game_data,
+        opponent_pair,
+        [white_summary, black_summary],
+    )
+    .await;
 }
 
-async fn game_session(db_pool: Pool<Postgres>, game_data: Game, opponent_pair: OpponentPair) {
-    let mut open_game = Gameplay::new(db_pool.clone(), game_data, opponent_pair);
-    // Start the game :D
-    let game_result = open_game.run().await;
-    // Check for errors
-    if let Err(error) = game_result {
-        // Game has encountered an error. Notify the active players.
-        let error = ServerMsg::Matchmaking(MatchmakingServerMsg::GameDropped(error.to_string()));
-        // This operation will probably foil for one of them, so we ignore the errors, as this is an error handler.
-        let _ = open_game.players.white_player.ws.send_as_text(&error).await;
-        let _ = open_game.players.black_player.ws.send_as_text(&error).await;
-        // We still want to panic on database errors though
-        remove_game(&db_pool, open_game.game_data).await.unwrap();
+/// Starts a game set up by the host, such as through a challenge, an invite or a seek.
+/// The colors are drawn at random unless the host picked one.
+pub(crate) async fn start_hosted_game(
+    db_pool: &Pool<Postgres>,
+    game_registry: &GameRegistry,
+    host: GamePlayer,
+    host_color: Option<PieceColor>,
+    guest: GamePlayer,
+    game_type: GameType,
+    rated: bool,
+) {
+    let host_color = host_color.unwrap_or_else(|| match rand::random() {
+        true => PieceColor::White,
+        false => PieceColor::Black,
+    });
+    let (white, black) = match host_color {
+        PieceColor::White => (host, guest),
+        PieceColor::Black => (guest, host),
     };
+    start_game_between(db_pool, game_registry, game_type, rated, white, black).await;
 }
 
-async fn ws_matchmaking(ws: GameWs, user_queue: UserQueue, user_id: i32) {
-    while let Ok(message) = ws.get().await {
-        if let Message::Close(_) = message {
-            let user_index = user_queue
-                .state
-                .lock()
-                .await
-                .iter()
-                .position(|player| player.id == user_id);
-            let Some(user_index) = user_index else {
-                continue;
-            };
-            user_queue.state.lock().await.remove(user_index);
-            return;
+/// Tells the player why their request was refused, if they are still there to hear it
+pub(crate) async fn send_error(ws: &GameWs, error: impl Into<ServerMsg>) {
+    let _ = ws.send_as_text(&error.into()).await;
+}
+
+/// Lets the players look for another game when theirs couldn't start
+async fn stop_waiting(game_registry: &GameRegistry, player_ids: [i32; 2]) {
+    for player_id in player_ids {
+        game_registry.sessions().stop_waiting(player_id).await;
+    }
+}
+
+async fn ws_matchmaking(
+    ws: GameWs,
+    user_queue: UserQueue,
+    sessions: SessionRegistry,
+    user_id: i32,
+) {
+    let mut heartbeat_check = interval(HEARTBEAT_INTERVAL);
+    let mut leaving = false;
+    while let Some(text) = next_text(&ws, &mut heartbeat_check).await {
+        if let Ok(MatchmakingClientMsg::Leave) = serde_json::from_str(&text) {
+            leaving = true;
+            break;
+        }
+    }
+    // A player who has been matched in the meantime is no longer in the queue
+    let removed = user_queue.remove(user_id).await.is_some();
+    // Does nothing for a player whose game has already started
+    sessions.stop_waiting(user_id).await;
+    if leaving && removed {
+        let _ = ws
+            .send_as_text(&ServerMsg::Matchmaking(MatchmakingServerMsg::Left))
+            .await;
+    }
+}
+
+/// Waits for the next text message from the player.
+/// Returns `None` once they leave or go silent.
+async fn next_text(ws: &GameWs, heartbeat_check: &mut Interval) -> Option<String> {
+    loop {
+        tokio::select! {
+            message = ws.get() => match message {
+                Ok(Message::Text(text)) => return Some(text),
+                Ok(Message::Close(_)) | Err(_) => return None,
+                Ok(_) => {}
+            },
+            _ = heartbeat_check.tick() => {
+                if !ws.is_alive().await {
+                    return None;
+                }
+            }
+        }
+    }
+}
+
+/// Waits until the player leaves or goes silent
+pub(crate) async fn wait_until_gone(ws: &GameWs) {
+    let mut heartbeat_check = interval(HEARTBEAT_INTERVAL);
+    while next_text(ws, &mut heartbeat_check).await.is_some() {}
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::time::timeout;
+
+    use super::*;
+    use crate::routes::{
+        game::{
+            channel_connection::{ChannelConnection, ChannelPeer},
+            gameplay::{
+                outcome::{GameEndReason, GameResult},
+                position::Position,
+                ws_message::{GameServerMsg, RematchServerMsg},
+            },
+            ws_messages::{ChessMove, GameClientMsg},
+        },
+        user::jwt::create_token,
+    };
+
+    /// How long the server may take to answer before the test gives up
+    const ANSWER_TIMEOUT: Duration = Duration::from_secs(5);
+
+    /// Waits for the first message from the server that `pick` accepts
+    async fn expect<T>(peer: &mut ChannelPeer, mut pick: impl FnMut(ServerMsg) -> Option<T>) -> T {
+        let answer = async {
+            while let Some(message) = peer.recv().await {
+                let Message::Text(text) = message else {
+                    continue;
+                };
+                if let Some(picked) = serde_json::from_str(&text).ok().and_then(&mut pick) {
+                    return picked;
+                }
+            }
+            panic!("The server hung up");
+        };
+        timeout(ANSWER_TIMEOUT, answer)
+            .await
+            .expect("The server didn't answer")
+    }
+
+    /// Needs `DATABASE_URL` to point at a server the test may create databases on,
+    /// and `JWT_SECRET` to sign the players' tokens with
+    #[sqlx::test(fixtures("players"))]
+    async fn queued_players_play_a_game_to_the_end(db_pool: Pool<Postgres>) {
+        let user_queue = UserQueue::default();
+        let game_registry = GameRegistry::default();
+        let player_ids = [1, 2];
+
+        let mut peers = Vec::new();
+        for player_id in player_ids {
+            let (ws, peer) = ChannelConnection::pair();
+            tokio::spawn(handle_ws(
+                GlobalState {
+                    db_pool: db_pool.clone(),
+                },
+                ws,
+                user_queue.clone(),
+                game_registry.clone(),
+            ));
+            peer.send(Message::Text(
+                create_token(player_id).expect("JWT_SECRET must be set"),
+            ))
+            .await
+            .unwrap();
+            peers.push(peer);
+        }
+        let (mut white, mut black) = (None, None);
+        for mut peer in peers {
+            let color = expect(&mut peer, |msg| match msg {
+                ServerMsg::Matchmaking(MatchmakingServerMsg::Success { color }) => Some(color),
+                _ => None,
+            })
+            .await;
+            match color {
+                PieceColor::White => white = Some(peer),
+                PieceColor::Black => black = Some(peer),
+            }
+        }
+        let (mut white, mut black) = (white.unwrap(), black.unwrap());
+
+        for peer in [&white, &black] {
+            peer.send_as_text(&GameClientMsg::Ack).await.unwrap();
+        }
+        // Moves only count once both players are in
+        expect(&mut white, |msg| match msg {
+            ServerMsg::Game(event) => {
+                matches!(event.msg, GameServerMsg::NewTurn(true)).then_some(())
+            }
+            _ => None,
+        })
+        .await;
+        let e2_e4 = ChessMove {
+            position_from: Position::new(4, 1),
+            position_to: Position::new(4, 3),
+        };
+        white
+            .send_as_text(&GameClientMsg::TurnEnd(e2_e4))
+            .await
+            .unwrap();
+        expect(&mut black, |msg| match msg {
+            ServerMsg::Game(event) => {
+                matches!(event.msg, GameServerMsg::PawnMove(..)).then_some(())
+            }
+            _ => None,
+        })
+        .await;
+        black.send_as_text(&GameClientMsg::Resign).await.unwrap();
+
+        for peer in [&mut white, &mut black] {
+            let (result, reason) = expect(peer, |msg| match msg {
+                ServerMsg::Game(event) => match event.msg {
+                    GameServerMsg::GameEnd { result, reason } => Some((result, reason)),
+                    _ => None,
+                },
+                _ => None,
+            })
+            .await;
+            assert_eq!(result, GameResult::WhiteWin);
+            assert_eq!(reason, GameEndReason::Resign);
+            // Offered once the game is over and done with
+            expect(peer, |msg| match msg {
+                ServerMsg::Rematch(RematchServerMsg::Available { .. }) => Some(()),
+                _ => None,
+            })
+            .await;
+        }
+        for player_id in player_ids {
+            assert_eq!(game_registry.sessions().game_of(player_id).await, None);
         }
     }
 }
@@ -1,11 +1,37 @@
 use serde::{Deserialize, Serialize};
 
-use crate::routes::game::piece_color::PieceColor;
+use crate::routes::game::{game_type::GameType, piece_color::PieceColor};
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub(crate) enum MatchmakingServerMsg {
     Searching,
-    Success { color: PieceColor },
+    /// Sent now and then while the player waits in the queue
+    QueueStatus {
+        /// Starting from 1 for the player who has waited the longest
+        position: usize,
+        queue_size: usize,
+        rating_window: i32,
+        /// `None` until enough games of the type have been matched
+        estimated_wait_secs: Option<u64>,
+    },
+    /// The player left the queue at their own request
+    Left,
+    Success {
+        color: PieceColor,
+    },
     Error(String),
     GameDropped(String),
 }
+
+#[derive(Deserialize, Debug)]
+pub(crate) enum MatchmakingClientMsg {
+    Leave,
+}
+
+/// First message on the matchmaking socket
+#[derive(Deserialize, Debug)]
+pub(crate) struct JoinRequest {
+    pub jwt: String,
+    #[serde(default)]
+    pub game_type: GameType,
+}
@@ -1,11 +1,26 @@
-use axum::{routing::get, Router};
+use axum::{
+    routing::{get, post},
+    Router,
+};
 
 use crate::ServerState;
 
+pub mod admin;
+pub mod challenge;
+// Only the tests drive the server from the same process so far
+#[cfg_attr(not(test), allow(dead_code))]
+pub mod channel_connection;
+pub mod game_registry;
+pub mod game_type;
 pub mod gameplay;
+pub mod invite;
+pub mod live;
+pub mod lobby;
 pub mod matchmaking;
 pub mod opponent_pair;
 pub mod piece_color;
+pub mod session_registry;
+pub mod spectate;
 pub mod ws;
 pub mod ws_messages;
 
@@ -13,4 +28,26 @@ pub fn routes() -> Router<ServerState> {
     Router::new()
         // Matchmaking WebSocket, dropped when match found
         .route("/", get(matchmaking::route_handler))
+        // Read-only WebSocket following a running game
+        .route("/spectate/:game_id", get(spectate::route_handler))
+        .route("/live", get(live::live_games))
+        // Spectator WebSocket following the highest rated running game
+        .route("/tv", get(live::featured_route_handler))
+        .route("/:game_id/abort", post(admin::abort_game))
+        // WebSocket of a player challenging someone, dropped when the game starts
+        .route("/challenge", get(challenge::route_handler))
+        .route("/challenge/incoming", post(challenge::incoming))
+        .route(
+            "/challenge/:challenge_id/accept",
+            get(challenge::accept_route_handler),
+        )
+        .route("/challenge/:challenge_id/decline", post(challenge::decline))
+        // WebSocket of a player hosting a private game, dropped when someone joins
+        .route("/invite", get(invite::route_handler))
+        .route("/invite/:code", get(invite::join_route_handler))
+        // WebSocket following the open seeks
+        .route("/lobby", get(lobby::route_handler))
+        // WebSocket of a player posting a seek, dropped when the game starts
+        .route("/seek", get(lobby::seek_route_handler))
+        .route("/seek/:seek_id/accept", get(lobby::accept_route_handler))
 }
@@ -1,7 +1,4 @@
-use super::{
-    gameplay::player::GamePlayer, matchmaking::matchmaking_state::MatchmakingPlayer,
-    piece_color::PieceColor,
-};
+use super::{gameplay::player::GamePlayer, piece_color::PieceColor};
 
 #[derive(Debug)]
 pub struct OpponentPair {
@@ -11,25 +8,35 @@ pub struct OpponentPair {
 }
 
 impl OpponentPair {
-    pub fn new(white_player: MatchmakingPlayer, black_player: MatchmakingPlayer) -> Self {
+    pub fn new(white_player: GamePlayer, black_player: GamePlayer) -> Self {
         Self {
-            white_player: GamePlayer::new(white_player.id, white_player.ws),
-            black_player: GamePlayer::new(black_player.id, black_player.ws),
+            white_player,
+            black_player,
             current_player_color: PieceColor::White,
         }
     }
 
-    pub fn get_active(&self) -> &GamePlayer {
-        match self.current_player_color {
+    pub fn get_by_color(&self, color: PieceColor) -> &GamePlayer {
+        match color {
             PieceColor::White => &self.white_player,
             PieceColor::Black => &self.black_player,
         }
     }
 
-    pub fn get_passive(&self) -> &GamePlayer {
-        match self.current_player_color {
-            PieceColor::White => &self.black_player,
-            PieceColor::Black => &self.white_player,
+    pub fn get_by_color_mut(&mut self, color: PieceColor) -> &mut GamePlayer {
+        match color {
+            PieceColor::White => &mut self.white_player,
+            PieceColor::Black => &mut self.black_player,
+        }
+    }
+
+    pub fn get_color_of(&self, player_id: i32) -> Option<PieceColor> {
+        if self.white_player.id == player_id {
+            Some(PieceColor::White)
+        } else if self.black_player.id == player_id {
+            Some(PieceColor::Black)
+        } else {
+            None
         }
     }
 
@@ -0,0 +1,79 @@
+//! What every user is doing, so that each of them takes part in one game at a time.
+//! A user who is waiting for an opponent can't start waiting again until they leave,
+//! while a player who connects again during a game takes the game over.
+
+use std::{collections::HashMap, sync::Arc};
+
+use anyhow::bail;
+use axum::extract::FromRef;
+use tokio::sync::Mutex;
+
+use crate::ServerState;
+
+/// Users missing from the registry are idle
+#[derive(PartialEq, Clone, Copy, Debug)]
+enum UserSession {
+    /// In the queue, or with an open challenge, invite or seek
+    Waiting,
+    Playing {
+        game_id: i32,
+    },
+}
+
+#[derive(Default, Clone, Debug)]
+pub struct SessionRegistry {
+    sessions: Arc<Mutex<HashMap<i32, UserSession>>>,
+}
+
+impl SessionRegistry {
+    /// Marks the idle user as waiting for an opponent
+    pub async fn start_waiting(&self, user_id: i32) -> anyhow::Result<()> {
+        let mut sessions = self.sessions.lock().await;
+        match sessions.get(&user_id) {
+            None => {
+                sessions.insert(user_id, UserSession::Waiting);
+                Ok(())
+            }
+            Some(UserSession::Waiting) => bail!("You are already waiting for a game"),
+            Some(UserSession::Playing { .. }) => bail!("You are already playing a game"),
+        }
+    }
+
+    /// Makes the user idle again, unless their game has started in the meantime
+    pub async fn stop_waiting(&self, user_id: i32) {
+        let mut sessions = self.sessions.lock().await;
+        if sessions.get(&user_id) == Some(&UserSession::Waiting) {
+            sessions.remove(&user_id);
+        }
+    }
+
+    pub async fn start_playing(&self, game_id: i32, player_ids: [i32; 2]) {
+        let mut sessions = self.sessions.lock().await;
+        for player_id in player_ids {
+            sessions.insert(player_id, UserSession::Playing { game_id });
+        }
+    }
+
+    pub async fn stop_playing(&self, game_id: i32, player_ids: [i32; 2]) {
+        let mut sessions = self.sessions.lock().await;
+        for player_id in player_ids {
+            if sessions.get(&player_id) == Some(&UserSession::Playing { game_id }) {
+                sessions.remove(&player_id);
+            }
+        }
+    }
+
+    /// The running game the player takes part in
+    pub async fn game_of(&self, player_id: i32) -> Option<i32> {
+        match self.sessions.lock().await.get(&player_id)? {
+            UserSession::Playing { game_id } => Some(*game_id),
+            UserSession::Waiting => None,
+        }
+    }
+}
+
+impl FromRef<ServerState> for SessionRegistry {
+    fn from_ref(input: &ServerState) -> Self {
+        input.game_registry.sessions().clone()
+    }
+}
@@ -0,0 +1,108 @@
+use anyhow::anyhow;
+use axum::{
+    extract::{
+        ws::{Message, WebSocket},
+        Path, State, WebSocketUpgrade,
+    },
+    response::Response,
+};
+use tokio::sync::mpsc;
+use ws_message::{SpectatorClientMsg, SpectatorServerMsg};
+
+use crate::routes::user::jwt::Claims;
+
+use super::{game_registry::GameRegistry, ws::GameWs, ws_messages::ServerMsg};
+
+pub mod ws_message;
+
+/// How many messages may wait for a slow spectator before they are dropped from the game
+pub const SPECTATOR_BUFFER: usize = 64;
+
+pub async fn route_handler(
+    ws: WebSocketUpgrade,
+    Path(game_id): Path<i32>,
+    State(game_registry): State<GameRegistry>,
+) -> Response {
+    ws.on_upgrade(move |socket: WebSocket| handle_ws(GameWs::new(socket), game_id, game_registry))
+}
+
+pub async fn handle_ws(ws: GameWs, game_id: i32, game_registry: GameRegistry) {
+    let (tx, rx) = mpsc::channel(SPECTATOR_BUFFER);
+    if !game_registry.spectate(game_id, tx).await {
+        let _ = ws
+            .send_as_text(&ServerMsg::Spectator(SpectatorServerMsg::GameNotFound))
+            .await;
+        return;
+    }
+    let mut spectator = Spectator::default();
+    spectator.follow(&ws, rx, &game_registry, game_id).await;
+}
+
+/// Someone watching games, who may identify themselves to take part in the chat
+#[derive(Default, Debug)]
+pub struct Spectator {
+    user_id: Option<i32>,
+}
+
+impl Spectator {
+    /// Passes the game's messages on until the game ends or the spectator leaves.
+    /// Returns `true` if the spectator is still connected.
+    pub async fn follow(
+        &mut self,
+        ws: &GameWs,
+        mut rx: mpsc::Receiver<ServerMsg>,
+        game_registry: &GameRegistry,
+        game_id: i32,
+    ) -> bool {
+        loop {
+            tokio::select! {
+                message = rx.recv() => {
+                    let Some(message) = message else {
+                        return true;
+                    };
+                    if ws.send_as_text(&message).await.is_err() {
+                        return false;
+                    }
+                }
+                message = ws.get() => {
+                    let Ok(message) = message else {
+                        return false;
+                    };
+                    let reply = match self.handle_message(message, game_registry, game_id).await {
+                        Ok(Some(reply)) => reply,
+                        Ok(None) => continue,
+                        Err(error) => SpectatorServerMsg::Error(error.to_string()),
+                    };
+                    if ws.send_as_text(&ServerMsg::Spectator(reply)).await.is_err() {
+                        return false;
+                    }
+                }
+            }
+        }
+    }
+
+    async fn handle_message(
+        &mut self,
+        message: Message,
+        game_registry: &GameRegistry,
+        game_id: i32,
+    ) -> anyhow::Result<Option<SpectatorServerMsg>> {
+        let Message::Text(message_text) = message else {
+            return Ok(None);
+        };
+        match serde_json::from_str::<SpectatorClientMsg>(&message_text)? {
+            SpectatorClientMsg::Auth(jwt_str) => {
+                let claims = Claims::try_from(jwt_str).map_err(|_| anyhow!("Invalid JWT!"))?;
+                self.user_id = Some(claims.sub);
+                Ok(Some(SpectatorServerMsg::Authenticated))
+            }
+            SpectatorClientMsg::Chat(text) => {
+                let user_id = self
+                    .user_id
+                    .ok_or(anyhow!("Log in to take part in the chat"))?;
+                game_registry.spectator_chat(game_id, user_id, text).await?;
+                Ok(None)
+            }
+        }
+    }
+}
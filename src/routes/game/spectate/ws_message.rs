@@ -0,0 +1,18 @@
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub(crate) enum SpectatorServerMsg {
+    GameNotFound,
+    /// The featured stream switched to the game with this id
+    Featured(i32),
+    NoLiveGames,
+    Authenticated,
+    Error(String),
+}
+
+#[derive(Deserialize, Debug)]
+pub(crate) enum SpectatorClientMsg {
+    /// Identifies the spectator with their JWT, which is needed to chat
+    Auth(String),
+    Chat(String),
+}
@@ -1,46 +1,71 @@
-use std::sync::Arc;
+use std::{env, sync::Arc, time::Duration};
 
 use anyhow::anyhow;
 use axum::extract::ws::{Message, WebSocket};
 use futures::{
+    future::BoxFuture,
     stream::{SplitSink, SplitStream},
-    SinkExt, StreamExt,
+    FutureExt, SinkExt, StreamExt,
 };
 use serde::Serialize;
-use tokio::sync::Mutex;
+use tokio::{
+    sync::{
+        mpsc::{self, error::SendTimeoutError},
+        Mutex,
+    },
+    task::JoinHandle,
+    time::{interval, Instant},
+};
 
-#[derive(Debug, Clone)]
-pub struct GameWs {
-    rx: Arc<Mutex<SplitStream<WebSocket>>>,
-    tx: Arc<Mutex<SplitSink<WebSocket, Message>>>,
+/// How often connections are pinged
+pub const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(10);
+const DEFAULT_HEARTBEAT_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// How long a connection may stay silent before it is considered dead.
+/// Set in seconds with the `HEARTBEAT_TIMEOUT` environment variable.
+pub fn heartbeat_timeout() -> Duration {
+    env::var("HEARTBEAT_TIMEOUT")
+        .ok()
+        .and_then(|seconds| seconds.parse().ok())
+        .map(Duration::from_secs)
+        .unwrap_or(DEFAULT_HEARTBEAT_TIMEOUT)
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Heartbeat {
+    pub last_seen: Instant,
+    pub ping_sent_at: Option<Instant>,
+    /// Round trip time of the last answered ping
+    pub latency: Option<Duration>,
+}
+
+/// Two-way message connection with a player or spectator
+pub trait PlayerConnection: Send + Sync + std::fmt::Debug {
+    /// Waits for the next message that isn't a ping or a pong
+    fn get(&self) -> BoxFuture<'_, anyhow::Result<Message>>;
+    fn send(&self, message: Message) -> BoxFuture<'_, anyhow::Result<()>>;
+    fn heartbeat(&self) -> BoxFuture<'_, Heartbeat>;
 }
 
+/// Shared handle to a connection, whatever it is carried over
+#[derive(Debug, Clone)]
+pub struct GameWs(Arc<dyn PlayerConnection>);
+
 impl GameWs {
     pub fn new(ws: WebSocket) -> Self {
-        let (tx, rx) = ws.split();
-        GameWs {
-            rx: Arc::new(Mutex::new(rx)),
-            tx: Arc::new(Mutex::new(tx)),
-        }
+        Self::from_connection(WebSocketConnection::new(ws))
+    }
+
+    pub fn from_connection(connection: impl PlayerConnection + 'static) -> Self {
+        Self(Arc::new(connection))
     }
 
     pub async fn get(&self) -> anyhow::Result<Message> {
-        self.rx
-            .lock()
-            .await
-            .next()
-            .await
-            .ok_or(anyhow!("No message"))?
-            .map_err(|err| anyhow!(err))
+        self.0.get().await
     }
 
     pub async fn send(&self, message: Message) -> anyhow::Result<()> {
-        self.tx
-            .lock()
-            .await
-            .send(message)
-            .await
-            .map_err(|err| anyhow!(err))
+        self.0.send(message).await
     }
 
     pub async fn send_as_text<T: Sized + Serialize>(&self, message: &T) -> anyhow::Result<()> {
@@ -48,4 +73,141 @@ impl GameWs {
         let message = Message::Text(serialized);
         self.send(message).await
     }
+
+    pub async fn heartbeat(&self) -> Heartbeat {
+        self.0.heartbeat().await
+    }
+
+    /// Whether the other side has been heard from within the heartbeat timeout
+    pub async fn is_alive(&self) -> bool {
+        self.heartbeat().await.last_seen.elapsed() < heartbeat_timeout()
+    }
+}
+
+/// Messages buffered in either direction of a WebSocket connection
+const CONNECTION_BUFFER: usize = 32;
+/// How long a send may wait for a slow client to make room in the buffer
+const SEND_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// WebSocket served by a reader and a writer task.
+/// Dropping the connection closes the socket.
+#[derive(Debug)]
+pub struct WebSocketConnection {
+    incoming: Mutex<mpsc::Receiver<Message>>,
+    outgoing: mpsc::Sender<Message>,
+    heartbeat: Arc<Mutex<Heartbeat>>,
+    reader: JoinHandle<()>,
+}
+
+impl WebSocketConnection {
+    pub fn new(ws: WebSocket) -> Self {
+        let (sink, stream) = ws.split();
+        let (incoming_tx, incoming_rx) = mpsc::channel(CONNECTION_BUFFER);
+        let (outgoing_tx, outgoing_rx) = mpsc::channel(CONNECTION_BUFFER);
+        let heartbeat = Arc::new(Mutex::new(Heartbeat {
+            last_seen: Instant::now(),
+            ping_sent_at: None,
+            latency: None,
+        }));
+        let reader = tokio::spawn(read_socket(stream, incoming_tx, heartbeat.clone()));
+        tokio::spawn(write_socket(sink, outgoing_rx, heartbeat.clone()));
+        WebSocketConnection {
+            incoming: Mutex::new(incoming_rx),
+            outgoing: outgoing_tx,
+            heartbeat,
+            reader,
+        }
+    }
+}
+
+impl Drop for WebSocketConnection {
+    fn drop(&mut self) {
+        // The writer closes the socket once it runs out of messages to send
+        self.reader.abort();
+    }
+}
+
+impl PlayerConnection for WebSocketConnection {
+    fn get(&self) -> BoxFuture<'_, anyhow::Result<Message>> {
+        async move {
+            self.incoming
+                .lock()
+                .await
+                .recv()
+                .await
+                .ok_or(anyhow!("No message"))
+        }
+        .boxed()
+    }
+
+    fn send(&self, message: Message) -> BoxFuture<'_, anyhow::Result<()>> {
+        async move {
+            self.outgoing
+                .send_timeout(message, SEND_TIMEOUT)
+                .await
+                .map_err(|error| match error {
+                    SendTimeoutError::Timeout(_) => anyhow!("The client can't keep up"),
+                    SendTimeoutError::Closed(_) => anyhow!("The connection is closed"),
+                })
+        }
+        .boxed()
+    }
+
+    fn heartbeat(&self) -> BoxFuture<'_, Heartbeat> {
+        async move { *self.heartbeat.lock().await }.boxed()
+    }
+}
+
+/// Passes messages on until the socket closes, recording pongs on the way
+async fn read_socket(
+    mut stream: SplitStream<WebSocket>,
+    incoming: mpsc::Sender<Message>,
+    heartbeat: Arc<Mutex<Heartbeat>>,
+) {
+    while let Some(Ok(message)) = stream.next().await {
+        {
+            let mut heartbeat = heartbeat.lock().await;
+            heartbeat.last_seen = Instant::now();
+            match message {
+                Message::Pong(_) => {
+                    if let Some(ping_sent_at) = heartbeat.ping_sent_at.take() {
+                        heartbeat.latency = Some(ping_sent_at.elapsed());
+                    }
+                    continue;
+                }
+                // Pings are answered by the WebSocket itself
+                Message::Ping(_) => continue,
+                _ => (),
+            }
+        }
+        // Waiting here leaves further messages to the socket's own buffers
+        if incoming.send(message).await.is_err() {
+            return;
+        }
+    }
+}
+
+/// Sends queued messages and pings until the connection is dropped or the socket fails
+async fn write_socket(
+    mut sink: SplitSink<WebSocket, Message>,
+    mut outgoing: mpsc::Receiver<Message>,
+    heartbeat: Arc<Mutex<Heartbeat>>,
+) {
+    let mut ping_interval = interval(HEARTBEAT_INTERVAL);
+    loop {
+        let message = tokio::select! {
+            message = outgoing.recv() => match message {
+                Some(message) => message,
+                None => break,
+            },
+            _ = ping_interval.tick() => {
+                heartbeat.lock().await.ping_sent_at = Some(Instant::now());
+                Message::Ping(Vec::new())
+            }
+        };
+        if sink.send(message).await.is_err() {
+            return;
+        }
+    }
+    let _ = sink.close().await;
 }
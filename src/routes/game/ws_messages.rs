@@ -1,9 +1,16 @@
 use serde::{Deserialize, Serialize};
 
 use super::{
-    gameplay::{position::Position, ws_message::GameServerMsg},
+    challenge::ws_message::ChallengeServerMsg,
+    gameplay::{
+        position::Position,
+        ws_message::{GameEvent, RematchServerMsg},
+    },
+    invite::ws_message::InviteServerMsg,
+    lobby::ws_message::LobbyServerMsg,
     matchmaking::ws_message::MatchmakingServerMsg,
     piece_color::PieceColor,
+    spectate::ws_message::SpectatorServerMsg,
 };
 
 #[derive(Deserialize, Debug, Serialize, Clone, Copy)]
@@ -27,14 +34,48 @@ impl ChessMove {
     }
 }
 
-#[derive(Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug)]
 pub(crate) enum GameClientMsg {
     TurnEnd(ChessMove),
     Ack,
+    RequestState,
+    /// Replays the messages sent after the one with the given sequence number
+    Resync(u64),
+    Chat(String),
+    Resign,
+    DrawOffer,
+    DrawAccept,
+    DrawDecline,
+    /// Asks for or accepts a rematch once the game is over
+    Rematch,
+    RematchDecline,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub(crate) enum ServerMsg {
     Matchmaking(MatchmakingServerMsg),
-    Game(GameServerMsg),
+    Game(GameEvent),
+    Spectator(SpectatorServerMsg),
+    Challenge(ChallengeServerMsg),
+    Invite(InviteServerMsg),
+    Lobby(LobbyServerMsg),
+    Rematch(RematchServerMsg),
+}
+
+impl From<ChallengeServerMsg> for ServerMsg {
+    fn from(msg: ChallengeServerMsg) -> Self {
+        ServerMsg::Challenge(msg)
+    }
+}
+
+impl From<InviteServerMsg> for ServerMsg {
+    fn from(msg: InviteServerMsg) -> Self {
+        ServerMsg::Invite(msg)
+    }
+}
+
+impl From<LobbyServerMsg> for ServerMsg {
+    fn from(msg: LobbyServerMsg) -> Self {
+        ServerMsg::Lobby(msg)
+    }
 }